@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS media_log (
+    date_time  DateTime,
+    chat_id    Int64,
+    message_id Int64,
+    sha256     String,
+    mime       LowCardinality(String),
+    size       UInt64,
+    path       String,
+    client_id  LowCardinality(UInt64)
+) ENGINE = ReplacingMergeTree(date_time)
+ORDER BY (chat_id, message_id)
+SETTINGS allow_suspicious_low_cardinality_types = 1;
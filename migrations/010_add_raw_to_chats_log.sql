@@ -0,0 +1 @@
+ALTER TABLE chats_log ADD COLUMN IF NOT EXISTS raw String DEFAULT '' CODEC(ZSTD(3));
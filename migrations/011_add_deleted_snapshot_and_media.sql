@@ -0,0 +1,11 @@
+ALTER TABLE chats_log ADD COLUMN IF NOT EXISTS media String DEFAULT '';
+
+ALTER TABLE telegram_messages_new ADD COLUMN IF NOT EXISTS media String DEFAULT '';
+
+ALTER TABLE deleted_log
+    ADD COLUMN IF NOT EXISTS message       String DEFAULT '',
+    ADD COLUMN IF NOT EXISTS chat_title    LowCardinality(String) DEFAULT '',
+    ADD COLUMN IF NOT EXISTS user_id       UInt64 DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS sender_name   String DEFAULT '',
+    ADD COLUMN IF NOT EXISTS original_date DateTime DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS media         String DEFAULT '';
@@ -0,0 +1,12 @@
+ALTER TABLE chats_log ADD COLUMN IF NOT EXISTS fwd_from_id Int64 DEFAULT 0;
+ALTER TABLE chats_log ADD COLUMN IF NOT EXISTS fwd_from_name String DEFAULT '';
+ALTER TABLE chats_log ADD COLUMN IF NOT EXISTS fwd_message_id Int32 DEFAULT 0;
+ALTER TABLE chats_log ADD COLUMN IF NOT EXISTS fwd_date UInt32 DEFAULT 0;
+ALTER TABLE chats_log ADD COLUMN IF NOT EXISTS fwd_saved_from_id Int64 DEFAULT 0;
+ALTER TABLE chats_log ADD COLUMN IF NOT EXISTS fwd_saved_from_message_id Int32 DEFAULT 0;
+ALTER TABLE telegram_messages_new ADD COLUMN IF NOT EXISTS fwd_from_id Int64 DEFAULT 0;
+ALTER TABLE telegram_messages_new ADD COLUMN IF NOT EXISTS fwd_from_name String DEFAULT '';
+ALTER TABLE telegram_messages_new ADD COLUMN IF NOT EXISTS fwd_message_id Int32 DEFAULT 0;
+ALTER TABLE telegram_messages_new ADD COLUMN IF NOT EXISTS fwd_date UInt32 DEFAULT 0;
+ALTER TABLE telegram_messages_new ADD COLUMN IF NOT EXISTS fwd_saved_from_id Int64 DEFAULT 0;
+ALTER TABLE telegram_messages_new ADD COLUMN IF NOT EXISTS fwd_saved_from_message_id Int32 DEFAULT 0;
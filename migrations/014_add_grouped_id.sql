@@ -0,0 +1,2 @@
+ALTER TABLE chats_log ADD COLUMN IF NOT EXISTS grouped_id Int64 DEFAULT 0;
+ALTER TABLE telegram_messages_new ADD COLUMN IF NOT EXISTS grouped_id Int64 DEFAULT 0;
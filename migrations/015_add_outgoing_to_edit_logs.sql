@@ -0,0 +1,2 @@
+ALTER TABLE edited_log ADD COLUMN IF NOT EXISTS outgoing Bool DEFAULT false;
+ALTER TABLE deleted_log ADD COLUMN IF NOT EXISTS outgoing Bool DEFAULT false;
@@ -0,0 +1 @@
+ALTER TABLE edited_log ADD COLUMN IF NOT EXISTS original_unknown Bool DEFAULT false;
@@ -0,0 +1,6 @@
+ALTER TABLE chats_log ADD COLUMN IF NOT EXISTS media_id Int64 DEFAULT 0;
+ALTER TABLE telegram_messages_new ADD COLUMN IF NOT EXISTS media_id Int64 DEFAULT 0;
+ALTER TABLE edited_log ADD COLUMN IF NOT EXISTS original_media String DEFAULT '';
+ALTER TABLE edited_log ADD COLUMN IF NOT EXISTS media String DEFAULT '';
+ALTER TABLE edited_log ADD COLUMN IF NOT EXISTS original_media_id Int64 DEFAULT 0;
+ALTER TABLE edited_log ADD COLUMN IF NOT EXISTS media_id Int64 DEFAULT 0;
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS backfill_cursors
+(
+    chat_id    Int64,
+    oldest_id  Int32,
+    done       Bool,
+    updated_at DateTime DEFAULT now()
+)
+ENGINE = ReplacingMergeTree(updated_at)
+ORDER BY chat_id;
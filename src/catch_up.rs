@@ -0,0 +1,99 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::{info, warn};
+
+/// Opt-in catch-up on updates missed while offline, enabled with `CATCH_UP=true`.
+///
+/// grammers then fetches the difference since the stored update state when the
+/// stream starts, each channel from its own stored pts, and the missed messages
+/// are stored by the normal handlers. They run no rules, commands or
+/// notifications, which were meant for the moment they arrived; deletions carry
+/// no date and are still notified. With a state older than
+/// `CATCH_UP_MAX_AGE_SECS` (default one day) there is no catch-up: the stored
+/// state is left as is and the gap stays; use `backfill` for it.
+static ENABLED: LazyLock<bool> = LazyLock::new(|| {
+    std::env::var("CATCH_UP")
+        .map(|v| v.parse().expect("CATCH_UP invalid"))
+        .unwrap_or(false)
+});
+
+static MAX_AGE: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("CATCH_UP_MAX_AGE_SECS")
+        .map(|v| v.parse().expect("CATCH_UP_MAX_AGE_SECS invalid"))
+        .unwrap_or(86_400)
+});
+
+/// The summary is logged once no recovered message arrived for this long.
+const QUIET: Duration = Duration::from_secs(10);
+
+/// Whether this run catches up; set by `should_catch_up`.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+/// Start of the update stream: messages sent before it were missed.
+static STARTED: AtomicU64 = AtomicU64::new(0);
+static RECOVERED: AtomicUsize = AtomicUsize::new(0);
+static LAST_RECOVERED: LazyLock<Mutex<Instant>> = LazyLock::new(|| Mutex::new(Instant::now()));
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Decide on catching up, given the date of the stored update state (0 if none).
+pub fn should_catch_up(state_date: i32) -> bool {
+    if !*ENABLED || state_date <= 0 {
+        return false;
+    }
+    let age = now().saturating_sub(state_date as u64);
+    if age > *MAX_AGE {
+        warn!(
+            "not catching up: last update state is {}h old, over CATCH_UP_MAX_AGE_SECS",
+            age / 3600
+        );
+        return false;
+    }
+    info!("catching up on {}m of missed updates", age / 60);
+    STARTED.store(now(), Ordering::Relaxed);
+    ACTIVE.store(true, Ordering::Relaxed);
+    true
+}
+
+/// Whether something dated `date` happened while we were offline.
+pub fn is_missed(date: i64) -> bool {
+    ACTIVE.load(Ordering::Relaxed) && date < STARTED.load(Ordering::Relaxed) as i64
+}
+
+/// Count a new message if it was sent while we were offline, and return
+/// whether it was.
+pub fn observe(date: i64) -> bool {
+    if !is_missed(date) {
+        return false;
+    }
+    RECOVERED.fetch_add(1, Ordering::Relaxed);
+    *LAST_RECOVERED.lock().unwrap() = Instant::now();
+    true
+}
+
+/// Log how many messages were recovered once the catch-up has settled.
+pub fn report() {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return;
+    }
+    *LAST_RECOVERED.lock().unwrap() = Instant::now();
+    tokio::spawn(async {
+        loop {
+            tokio::time::sleep(QUIET).await;
+            if LAST_RECOVERED.lock().unwrap().elapsed() >= QUIET {
+                break;
+            }
+        }
+        ACTIVE.store(false, Ordering::Relaxed);
+        info!(
+            "caught up: {} missed messages recovered",
+            RECOVERED.load(Ordering::Relaxed)
+        );
+    });
+}
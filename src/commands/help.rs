@@ -0,0 +1,27 @@
+use super::{Command, Context, Reply, registered};
+
+pub const COMMAND: Command = Command {
+    name: "help",
+    usage: "",
+    about: "list commands",
+    run,
+};
+
+fn run<'a>(_ctx: &'a Context, _args: &'a str) -> Reply<'a> {
+    Box::pin(async move {
+        let lines: Vec<String> = registered()
+            .iter()
+            .map(|c| {
+                let usage = if c.usage.is_empty() {
+                    format!("{}{}", *super::PREFIX, c.name)
+                } else {
+                    format!("{}{} {}", *super::PREFIX, c.name, c.usage)
+                };
+                // Not starting with the prefix, so the reply can't be taken
+                // for a command.
+                format!("• {usage} — {}", c.about)
+            })
+            .collect();
+        Ok(lines.join("\n"))
+    })
+}
@@ -0,0 +1,32 @@
+use super::{Command, Context, Reply};
+use crate::utils::log_ignore;
+
+pub const COMMAND: Command = Command {
+    name: "ignore",
+    usage: "[chat id]",
+    about: "toggle console logging for a chat (until restart); without arguments, list ignored chats",
+    run,
+};
+
+fn run<'a>(_ctx: &'a Context, args: &'a str) -> Reply<'a> {
+    Box::pin(async move {
+        if args.is_empty() {
+            let ids = log_ignore::ignored();
+            if ids.is_empty() {
+                return Ok("no chats ignored".to_string());
+            }
+            return Ok(ids
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"));
+        }
+        let id: i64 = args.parse().map_err(|_| format!("not a chat id: {args}"))?;
+        let chat_id = crate::utils::peer::bare_id(id);
+        Ok(if log_ignore::toggle(chat_id) {
+            format!("ignoring {id}")
+        } else {
+            format!("logging {id} again")
+        })
+    })
+}
@@ -0,0 +1,121 @@
+mod help;
+mod ignore;
+mod stats;
+mod whois;
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{LazyLock, Mutex, RwLock};
+
+use grammers_client::Client;
+use grammers_client::update::Message;
+use grammers_session::types::PeerKind;
+use log::{error, info};
+
+use crate::utils::text::truncate_message;
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+pub type Reply<'a> = Pin<Box<dyn Future<Output = Result<String, Error>> + Send + 'a>>;
+
+/// Commands are our own messages in Saved Messages starting with `COMMAND_PREFIX`
+/// (default "."), e.g. `.stats`. The result is sent as a reply.
+static PREFIX: LazyLock<String> =
+    LazyLock::new(|| std::env::var("COMMAND_PREFIX").unwrap_or_else(|_| ".".to_string()));
+
+/// Ids of our latest replies. They are Saved Messages messages of our own too,
+/// and must not run as commands even if they start with the prefix.
+static REPLIES: Mutex<VecDeque<i32>> = Mutex::new(VecDeque::new());
+const REPLIES_KEPT: usize = 64;
+
+static REGISTRY: LazyLock<RwLock<Vec<Command>>> = LazyLock::new(|| {
+    RwLock::new(vec![
+        help::COMMAND,
+        stats::COMMAND,
+        whois::COMMAND,
+        ignore::COMMAND,
+    ])
+});
+
+#[derive(Clone, Copy)]
+pub struct Command {
+    pub name: &'static str,
+    /// Arguments, shown by `help`.
+    pub usage: &'static str,
+    pub about: &'static str,
+    pub run: for<'a> fn(&'a Context, &'a str) -> Reply<'a>,
+}
+
+pub struct Context {
+    pub client: Client,
+    pub client_id: u64,
+    /// The command message itself.
+    pub message: grammers_client::message::Message,
+}
+
+/// Add a command, replacing a registered one with the same name.
+pub fn register(command: Command) {
+    let mut registry = REGISTRY.write().unwrap();
+    registry.retain(|c| c.name != command.name);
+    registry.push(command);
+}
+
+/// Start the uptime clock and log the prefix.
+pub fn init() {
+    LazyLock::force(&stats::STARTED);
+    info!(
+        "commands enabled in Saved Messages with prefix {:?}",
+        *PREFIX
+    );
+}
+
+fn registered() -> Vec<Command> {
+    REGISTRY.read().unwrap().clone()
+}
+
+/// Run the command in an outgoing Saved Messages message, if it is one.
+pub fn dispatch(client: &Client, message: &Message, client_id: u64) {
+    let peer = message.peer_id();
+    if !matches!(peer.kind(), PeerKind::User) || peer.bare_id_unchecked() != client_id as i64 {
+        return;
+    }
+    let Some(line) = message.text().strip_prefix(PREFIX.as_str()) else {
+        return;
+    };
+    if REPLIES.lock().unwrap().contains(&message.id()) {
+        return;
+    }
+    let (name, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let Some(command) = registered().into_iter().find(|c| c.name == name) else {
+        return;
+    };
+
+    info!(
+        "\x1b[96m{:<8} {:>8} {}\x1b[0m",
+        "command",
+        message.id(),
+        line
+    );
+    let ctx = Context {
+        client: client.clone(),
+        client_id,
+        message: grammers_client::message::Message::clone(message),
+    };
+    let args = args.trim().to_string();
+    tokio::spawn(async move {
+        let text = match (command.run)(&ctx, &args).await {
+            Ok(text) => text,
+            Err(e) => format!("{} failed: {e}", command.name),
+        };
+        match ctx.message.reply(truncate_message(text)).await {
+            Ok(reply) => {
+                let mut replies = REPLIES.lock().unwrap();
+                replies.push_back(reply.id());
+                if replies.len() > REPLIES_KEPT {
+                    replies.pop_front();
+                }
+            }
+            Err(e) => error!("failed to reply to command {}: {e}", command.name),
+        }
+    });
+}
@@ -0,0 +1,36 @@
+use std::sync::LazyLock;
+use std::time::Instant;
+
+use super::{Command, Context, Reply};
+use crate::db;
+
+pub const COMMAND: Command = Command {
+    name: "stats",
+    usage: "",
+    about: "uptime, cache and buffer sizes",
+    run,
+};
+
+/// Taken when the command registry is first used, right after startup.
+pub static STARTED: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+fn run<'a>(_ctx: &'a Context, _args: &'a str) -> Reply<'a> {
+    Box::pin(async move {
+        let uptime = STARTED.elapsed().as_secs() as i32;
+        Ok(format!(
+            "uptime: {}\n\
+             cached messages: {}\n\
+             ignored chats: {}\n\
+             pending rows: incoming {}, outgoing {}, edited {}, deleted {}, reactions {}, media {}",
+            crate::utils::media_description::format_human_duration(uptime),
+            crate::message_cache::len(),
+            crate::utils::log_ignore::ignored().len(),
+            db::INCOMING_BUF.pending().await,
+            db::OUTGOING_BUF.pending().await,
+            db::EDITED_BUF.pending().await,
+            db::DELETED_BUF.pending().await,
+            db::REACTIONS_BUF.pending().await,
+            db::MEDIA_BUF.pending().await,
+        ))
+    })
+}
@@ -0,0 +1,65 @@
+use grammers_client::peer::Peer;
+
+use super::{Command, Context, Reply};
+
+pub const COMMAND: Command = Command {
+    name: "whois",
+    usage: "<@username | chat id>",
+    about: "show who a user or chat is; without arguments, the sender of the replied message",
+    run,
+};
+
+fn run<'a>(ctx: &'a Context, args: &'a str) -> Reply<'a> {
+    Box::pin(async move {
+        let peer = if let Some(username) = args.strip_prefix('@') {
+            ctx.client
+                .resolve_username(username)
+                .await?
+                .ok_or_else(|| format!("@{username} not found"))?
+        } else if !args.is_empty() {
+            let id: i64 = args.parse().map_err(|_| format!("not a chat id: {args}"))?;
+            crate::utils::peer::resolve_peer(&ctx.client, id).await?
+        } else {
+            let reply = ctx
+                .client
+                .get_reply_to_message(&ctx.message)
+                .await?
+                .ok_or("reply to a message or give a username or id")?;
+            reply
+                .sender()
+                .ok_or("sender of the replied message is unknown")?
+                .clone()
+        };
+        Ok(describe(&peer))
+    })
+}
+
+fn describe(peer: &Peer) -> String {
+    let id = peer.id();
+    let kind = match peer {
+        Peer::User(_) => "user",
+        Peer::Group(_) => "group",
+        Peer::Channel(_) => "channel",
+        Peer::Community(_) => "community",
+    };
+    let mut lines = vec![
+        format!("name: {}", peer.name().unwrap_or_default()),
+        format!("id: {} ({kind})", id.bot_api_dialog_id_unchecked()),
+    ];
+    let usernames: Vec<String> = peer
+        .username()
+        .into_iter()
+        .chain(peer.usernames())
+        .map(|u| format!("@{u}"))
+        .collect();
+    if !usernames.is_empty() {
+        lines.push(format!("usernames: {}", usernames.join(" ")));
+    }
+    if let Some(title) = crate::message_cache::chat_title(id.bare_id_unchecked()) {
+        lines.push(format!("last seen title: {title}"));
+    }
+    if crate::utils::log_ignore::is_log_ignored(id.bare_id_unchecked()) {
+        lines.push("log ignored".to_string());
+    }
+    lines.join("\n")
+}
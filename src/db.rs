@@ -1,7 +1,15 @@
 use clickhouse::{Client, Row};
-use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
 use std::sync::LazyLock;
-use tokio::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, Notify};
+
+use crate::message_cache::CachedMessage;
+use crate::storage::{MessageContent, Storage, StorageError, StoredMessage, storage};
 
 static CLICKHOUSE: LazyLock<Client> = LazyLock::new(|| {
     Client::default()
@@ -15,138 +23,532 @@ pub fn clickhouse() -> &'static Client {
     &CLICKHOUSE
 }
 
+/// Attempts per batch before its rows are moved to the dead-letter file.
+const MAX_FLUSH_ATTEMPTS: u32 = 12;
+const RETRY_BASE: Duration = Duration::from_secs(10);
+const RETRY_MAX: Duration = Duration::from_secs(30 * 60);
+
+/// Woken when a buffer crosses its row or byte threshold.
+static FLUSH_REQUESTED: LazyLock<Notify> = LazyLock::new(Notify::new);
+/// Woken after every flush so pushes blocked on a full buffer can re-check.
+static FLUSHED: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+/// Wait until some buffer asks for an early flush.
+pub async fn flush_requested() {
+    FLUSH_REQUESTED.notified().await;
+}
+
+/// When a buffer is flushed early, and when pushing into it blocks.
+pub struct BufferLimits {
+    /// Flush once this many rows are buffered.
+    pub max_rows: usize,
+    /// Flush once the buffered rows take roughly this many bytes.
+    pub max_bytes: usize,
+    /// Flush once the oldest buffered row is this old.
+    pub max_age: Duration,
+    /// Hard cap on rows held in memory (buffered plus re-queued). See `WriteBuffer::push`.
+    pub cap_rows: usize,
+    /// Hard cap on the approximate size of the buffered rows.
+    pub cap_bytes: usize,
+}
+
+impl BufferLimits {
+    pub const DEFAULT: Self = Self {
+        max_rows: 5_000,
+        max_bytes: 8 << 20,
+        max_age: Duration::from_secs(60),
+        cap_rows: 50_000,
+        cap_bytes: 64 << 20,
+    };
+}
+
 pub struct WriteBuffer<T: Send + 'static> {
     table: &'static str,
-    buffer: Mutex<Vec<T>>,
+    limits: BufferLimits,
+    buffer: Mutex<Buffered<T>>,
+    retry: Mutex<Vec<PendingBatch<T>>>,
+    /// Rows of re-queued batches that only live in memory (not spooled).
+    queued_rows: AtomicUsize,
+}
+
+struct Buffered<T> {
+    rows: Vec<T>,
+    /// Indices into `rows` that could not be appended to the spool.
+    unspooled: BTreeSet<usize>,
+    /// Approximate size of `rows`, measured as serialized JSON.
+    bytes: usize,
+    oldest: Option<Instant>,
+}
+
+impl<T> Buffered<T> {
+    const fn new() -> Self {
+        Self {
+            rows: Vec::new(),
+            unspooled: BTreeSet::new(),
+            bytes: 0,
+            oldest: None,
+        }
+    }
+}
+
+/// A batch waiting for ClickHouse, re-queued with exponential backoff on failure.
+/// Spooled batches are read back from disk on every attempt instead of being held
+/// in memory.
+struct PendingBatch<T> {
+    rows: Vec<T>,
+    /// Spool batch backing this batch, removed once it is inserted.
+    spool: Option<PathBuf>,
+    attempts: u32,
+    next_attempt: Instant,
 }
 
 impl<T> WriteBuffer<T>
 where
-    T: Serialize + Send + 'static,
+    T: Serialize + DeserializeOwned + Indexed + Send + Sync + 'static,
     for<'a> T: Row<Value<'a> = T>,
 {
-    pub const fn new(table: &'static str) -> Self {
+    pub const fn new(table: &'static str, limits: BufferLimits) -> Self {
         Self {
             table,
-            buffer: Mutex::const_new(Vec::new()),
+            limits,
+            buffer: Mutex::const_new(Buffered::new()),
+            retry: Mutex::const_new(Vec::new()),
+            queued_rows: AtomicUsize::new(0),
         }
     }
 
+    /// Buffer a row. At the hard cap, memory is not allowed to grow any further:
+    /// without a spool the oldest rows are moved to the dead-letter file to make
+    /// room. With a spool, flushes empty the buffer even while storage is down, so
+    /// the cap is only reached when the spool itself can't be written; `push` then
+    /// waits for a flush, which stalls the whole update loop until storage is back.
     pub async fn push(&self, row: T) {
-        self.buffer.lock().await.push(row);
+        row.index();
+        let line = serde_json::to_string(&row).unwrap_or_default();
+        let mut warned = false;
+        let mut buf = loop {
+            let buf = self.buffer.lock().await;
+            let held = buf.rows.len() + self.queued_rows.load(Ordering::Relaxed);
+            if held < self.limits.cap_rows && buf.bytes < self.limits.cap_bytes {
+                break buf;
+            }
+            drop(buf);
+            if !crate::spool::enabled() {
+                self.shed_oldest().await;
+                continue;
+            }
+            if !warned {
+                log::warn!("buffer for {} is full ({held} rows), waiting for flush", self.table);
+                warned = true;
+            }
+            FLUSH_REQUESTED.notify_one();
+            let _ = tokio::time::timeout(Duration::from_secs(1), FLUSHED.notified()).await;
+        };
+
+        if let Err(e) = crate::spool::append(self.table, &line) {
+            log::error!("spool append to {}: {e}", self.table);
+            let idx = buf.rows.len();
+            buf.unspooled.insert(idx);
+        }
+        buf.rows.push(row);
+        buf.bytes += line.len();
+        buf.oldest.get_or_insert_with(Instant::now);
+
+        if buf.rows.len() >= self.limits.max_rows || buf.bytes >= self.limits.max_bytes {
+            FLUSH_REQUESTED.notify_one();
+        }
+    }
+
+    /// Move the oldest held rows to the dead-letter file: the oldest re-queued
+    /// batch, or a tenth of the buffer when nothing is re-queued.
+    async fn shed_oldest(&self) {
+        let mut queue = self.retry.lock().await;
+        let rows = if queue.is_empty() {
+            drop(queue);
+            let mut buf = self.buffer.lock().await;
+            let n = (buf.rows.len() / 10).max(1).min(buf.rows.len());
+            let rows: Vec<T> = buf.rows.drain(..n).collect();
+            let bytes: usize = rows
+                .iter()
+                .map(|r| serde_json::to_string(r).map_or(0, |l| l.len()))
+                .sum();
+            buf.bytes = buf.bytes.saturating_sub(bytes);
+            if buf.rows.is_empty() {
+                buf.oldest = None;
+            }
+            rows
+        } else {
+            let batch = queue.remove(0);
+            self.queued_rows.fetch_sub(batch.rows.len(), Ordering::Relaxed);
+            batch.rows
+        };
+        log::error!(
+            "buffer for {} is full, moving the {} oldest rows to the dead-letter file",
+            self.table,
+            rows.len()
+        );
+        crate::spool::dead_letter(self.table, &rows);
     }
 
     pub async fn find_last<F, R>(&self, f: F) -> Option<R>
     where
         F: Fn(&T) -> Option<R>,
     {
-        self.buffer.lock().await.iter().rev().find_map(f)
+        self.buffer.lock().await.rows.iter().rev().find_map(f)
     }
 
+    /// Rows not yet written: buffered plus re-queued in memory.
+    pub async fn pending(&self) -> usize {
+        self.buffer.lock().await.rows.len() + self.queued_rows.load(Ordering::Relaxed)
+    }
+
+    /// Whether a threshold is crossed or a re-queued batch is due for retry.
+    pub async fn is_due(&self) -> bool {
+        {
+            let buf = self.buffer.lock().await;
+            if buf.rows.len() >= self.limits.max_rows
+                || buf.bytes >= self.limits.max_bytes
+                || buf.oldest.is_some_and(|t| t.elapsed() >= self.limits.max_age)
+            {
+                return true;
+            }
+        }
+        // A locked queue means a flush is already running.
+        let Ok(queue) = self.retry.try_lock() else {
+            return false;
+        };
+        let now = Instant::now();
+        queue.iter().any(|b| b.next_attempt <= now)
+    }
+
+    /// Insert buffered rows and every retry batch that is due; returns rows written.
     pub async fn flush(&self) -> usize {
-        let rows: Vec<T> = {
+        self.flush_batches(false).await
+    }
+
+    /// Like `flush`, but also retries batches still in backoff. Used on shutdown.
+    pub async fn drain(&self) -> usize {
+        self.flush_batches(true).await
+    }
+
+    /// Without a spool the in-memory rows form a new batch. With a spool the sealed
+    /// batch files are the source of truth: every batch on disk (including ones left
+    /// over from a previous run) is queued, and each file is deleted only after
+    /// ClickHouse acknowledges it. Batches are tried oldest first; a transient
+    /// failure re-queues the batch with backoff and stops this round, a permanent
+    /// rejection or an exhausted retry budget moves its rows to the dead-letter file.
+    async fn flush_batches(&self, ignore_backoff: bool) -> usize {
+        let mut queue = self.retry.lock().await;
+        let now = Instant::now();
+
+        let Buffered { rows, unspooled, .. } = {
             let mut buf = self.buffer.lock().await;
-            if buf.is_empty() {
-                return 0;
+            if let Err(e) = crate::spool::seal(self.table) {
+                log::error!("spool seal for {}: {e}", self.table);
             }
-            std::mem::take(&mut *buf)
+            std::mem::replace(&mut *buf, Buffered::new())
         };
-        let count = rows.len();
-        match clickhouse().insert::<T>(self.table).await {
-            Ok(mut insert) => {
-                for row in rows {
-                    if let Err(e) = insert.write(&row).await {
-                        log::error!("buffer write to {}: {e}", self.table);
-                        return 0;
+
+        let mut fresh = Vec::new();
+        if crate::spool::enabled() {
+            fresh.extend(
+                rows.into_iter()
+                    .enumerate()
+                    .filter(|(i, _)| unspooled.contains(i))
+                    .map(|(_, row)| row),
+            );
+            for path in crate::spool::batches(self.table) {
+                if queue.iter().any(|b| b.spool.as_ref() == Some(&path)) {
+                    continue;
+                }
+                queue.push(PendingBatch {
+                    rows: Vec::new(),
+                    spool: Some(path),
+                    attempts: 0,
+                    next_attempt: now,
+                });
+            }
+        } else {
+            fresh = rows;
+        }
+        if !fresh.is_empty() {
+            queue.push(PendingBatch {
+                rows: fresh,
+                spool: None,
+                attempts: 0,
+                next_attempt: now,
+            });
+        }
+
+        let mut flushed = 0;
+        let mut i = 0;
+        while i < queue.len() {
+            if !ignore_backoff && queue[i].next_attempt > now {
+                i += 1;
+                continue;
+            }
+
+            let spooled = match &queue[i].spool {
+                Some(path) => match crate::spool::read::<T>(path) {
+                    Ok(rows) => Some(rows),
+                    Err(e) => {
+                        log::error!("spool read {}: {e}", path.display());
+                        i += 1;
+                        continue;
                     }
+                },
+                None => None,
+            };
+            let batch = &mut queue[i];
+            let rows = spooled.as_deref().unwrap_or(&batch.rows);
+
+            let err = if rows.is_empty() {
+                None
+            } else {
+                self.insert(rows).await.err()
+            };
+            let Some(err) = err else {
+                flushed += rows.len();
+                let batch = queue.remove(i);
+                if let Some(path) = &batch.spool {
+                    crate::spool::remove(path);
                 }
-                if let Err(e) = insert.end().await {
-                    log::error!("buffer flush to {}: {e}", self.table);
-                    0
-                } else {
-                    count
+                continue;
+            };
+
+            batch.attempts += 1;
+            if is_permanent(&err) || batch.attempts >= MAX_FLUSH_ATTEMPTS {
+                log::error!(
+                    "buffer flush to {} gave up after {} attempt(s): {err}",
+                    self.table,
+                    batch.attempts
+                );
+                crate::spool::dead_letter(self.table, rows);
+                let batch = queue.remove(i);
+                if let Some(path) = &batch.spool {
+                    crate::spool::remove(path);
                 }
+                continue;
             }
-            Err(e) => {
-                log::error!("buffer insert to {}: {e}", self.table);
-                0
-            }
+
+            let delay = RETRY_BASE
+                .saturating_mul(1 << (batch.attempts - 1).min(16))
+                .min(RETRY_MAX);
+            batch.next_attempt = Instant::now() + delay;
+            log::warn!(
+                "buffer flush to {} failed (attempt {}/{}): {err}, {} rows re-queued, retry in {}s",
+                self.table,
+                batch.attempts,
+                MAX_FLUSH_ATTEMPTS,
+                rows.len(),
+                delay.as_secs()
+            );
+            // Storage is most likely unreachable, don't hammer it with the rest.
+            break;
         }
+
+        let queued = queue.iter().map(|b| b.rows.len()).sum();
+        self.queued_rows.store(queued, Ordering::Relaxed);
+        drop(queue);
+        FLUSHED.notify_waiters();
+
+        flushed
+    }
+
+    async fn insert(&self, rows: &[T]) -> crate::storage::Result<()> {
+        storage().insert(self.table, rows).await
     }
 }
 
-pub static INCOMING_BUF: WriteBuffer<IncomingMessage> = WriteBuffer::new("chats_log");
-pub static EDITED_BUF: WriteBuffer<EditedMessage> = WriteBuffer::new("edited_log");
-pub static DELETED_BUF: WriteBuffer<DeletedMessage> = WriteBuffer::new("deleted_log");
+/// Errors that retrying cannot fix: the server rejected the rows because they
+/// don't match the table schema. Only ClickHouse reports these; other backends
+/// adapt their tables to the rows.
+fn is_permanent(e: &StorageError) -> bool {
+    let StorageError::ClickHouse(clickhouse::error::Error::BadResponse(msg)) = e else {
+        return false;
+    };
+    // The server's exception text starts with "Code: N."
+    let code = msg
+        .trim_start()
+        .strip_prefix("Code: ")
+        .and_then(|rest| rest.split(|c: char| !c.is_ascii_digit()).next())
+        .and_then(|code| code.parse::<u32>().ok());
+    // NOT_FOUND_COLUMN_IN_BLOCK, NO_SUCH_COLUMN_IN_TABLE, CANNOT_PARSE_INPUT_ASSERTION_FAILED,
+    // CANNOT_READ_ALL_DATA, UNKNOWN_IDENTIFIER, TYPE_MISMATCH, UNKNOWN_TABLE, SYNTAX_ERROR,
+    // CANNOT_CONVERT_TYPE, INCORRECT_DATA
+    matches!(
+        code,
+        Some(10 | 16 | 27 | 33 | 47 | 53 | 60 | 62 | 70 | 117)
+    )
+}
+
+pub static INCOMING_BUF: WriteBuffer<IncomingMessage> = WriteBuffer::new(
+    "chats_log",
+    BufferLimits {
+        max_rows: 20_000,
+        max_bytes: 32 << 20,
+        cap_rows: 200_000,
+        cap_bytes: 256 << 20,
+        ..BufferLimits::DEFAULT
+    },
+);
+pub static OUTGOING_BUF: WriteBuffer<OutgoingMessage> =
+    WriteBuffer::new("telegram_messages_new", BufferLimits::DEFAULT);
+pub static EDITED_BUF: WriteBuffer<EditedMessage> =
+    WriteBuffer::new("edited_log", BufferLimits::DEFAULT);
+pub static DELETED_BUF: WriteBuffer<DeletedMessage> =
+    WriteBuffer::new("deleted_log", BufferLimits::DEFAULT);
+pub static REACTIONS_BUF: WriteBuffer<Reactions> =
+    WriteBuffer::new("reactions_log", BufferLimits::DEFAULT);
+pub static MEDIA_BUF: WriteBuffer<MediaFile> =
+    WriteBuffer::new("media_log", BufferLimits::DEFAULT);
 
 pub struct MessageInfo {
     pub message: String,
     pub chat_title: String,
     pub first_name: String,
+    /// First and last name; empty for our own messages.
+    pub sender_name: String,
+    pub user_id: u64,
+    /// When the message was sent, 0 if unknown.
+    pub date_time: u32,
+    pub media: String,
+    /// See `media_description::media_id`; 0 if unknown.
+    pub media_id: i64,
+    /// One of our own messages, found in telegram_messages_new.
+    pub outgoing: bool,
 }
 
 /// Find message info by chat_id + message_id.
-/// Priority: buffers (EDITED_BUF / INCOMING_BUF) → ClickHouse.
+/// Priority: message cache → buffers (EDITED_BUF / INCOMING_BUF / OUTGOING_BUF) → storage.
 pub async fn find_message(chat_id: i64, message_id: i64) -> MessageInfo {
-    let from_incoming = INCOMING_BUF
+    if let Some(m) = crate::message_cache::get(chat_id, message_id) {
+        return MessageInfo {
+            sender_name: m.sender().unwrap_or_default(),
+            message: m.text,
+            chat_title: m.chat_title,
+            first_name: m.first_name,
+            user_id: m.user_id,
+            date_time: m.date_time,
+            media: m.media,
+            media_id: m.media_id,
+            outgoing: m.outgoing,
+        };
+    }
+
+    let mut outgoing = false;
+    let mut buffered = INCOMING_BUF
         .find_last(|m| {
-            (m.chat_id == chat_id && m.message_id == message_id)
-                .then(|| (m.message.clone(), m.chat_title.clone(), m.first_name.clone()))
+            (m.chat_id == chat_id && m.message_id == message_id).then(|| StoredMessage {
+                message: m.message.clone(),
+                chat_title: m.chat_title.clone(),
+                first_name: m.first_name.clone(),
+                second_name: m.second_name.clone(),
+                user_id: m.user_id,
+                date_time: m.date_time,
+                media: m.media.clone(),
+                media_id: m.media_id,
+            })
         })
         .await;
+    if buffered.is_none() {
+        buffered = OUTGOING_BUF
+            .find_last(|m| {
+                (m.id == chat_id && m.message_id == message_id as u64).then(|| StoredMessage {
+                    message: m.message.clone(),
+                    chat_title: m.title.clone(),
+                    first_name: String::new(),
+                    second_name: String::new(),
+                    user_id: m.client_id,
+                    date_time: m.date_time,
+                    media: m.media.clone(),
+                    media_id: m.media_id,
+                })
+            })
+            .await;
+        outgoing = buffered.is_some();
+    }
+    let is_buffered = buffered.is_some();
+    let stored = match buffered {
+        Some(m) => Some(m),
+        None => match storage().incoming(chat_id, message_id).await.ok().flatten() {
+            Some(m) => Some(m),
+            None => {
+                let m = storage().outgoing(chat_id, message_id).await.ok().flatten();
+                outgoing = m.is_some();
+                m
+            }
+        },
+    };
 
-    let message = if let Some(msg) = EDITED_BUF
+    let edited = EDITED_BUF
         .find_last(|e| {
-            (e.chat_id == chat_id && e.message_id == message_id).then(|| e.message.clone())
+            (e.chat_id == chat_id && e.message_id == message_id)
+                .then(|| (e.message.clone(), e.media.clone(), e.media_id))
         })
-        .await
-    {
-        msg
-    } else if let Some((msg, _, _)) = from_incoming.as_ref() {
-        msg.clone()
+        .await;
+    // The newest content: a buffered edit, else the buffered row itself, else
+    // the last edit or row in storage.
+    let latest = if let Some((message, media, media_id)) = edited {
+        Some(MessageContent {
+            message,
+            media,
+            media_id,
+        })
+    } else if is_buffered {
+        None
     } else {
-        clickhouse()
-            .query(
-                "SELECT message FROM (\
-                    SELECT message, 1 AS p, date_time FROM edited_log WHERE chat_id = ? AND message_id = ? \
-                    UNION ALL \
-                    SELECT message, 2 AS p, date_time FROM chats_log WHERE chat_id = ? AND message_id = ?\
-                ) ORDER BY p, date_time DESC LIMIT 1",
-            )
-            .bind(chat_id)
-            .bind(message_id)
-            .bind(chat_id)
-            .bind(message_id)
-            .fetch_one::<String>()
+        storage()
+            .latest_content(chat_id, message_id)
             .await
-            .unwrap_or_default()
+            .ok()
+            .flatten()
     };
 
-    let (chat_title, first_name) = if let Some((_, t, f)) = from_incoming {
-        (t, f)
-    } else {
-        let title = clickhouse()
-            .query("SELECT chat_title FROM chats_log WHERE chat_id = ? ORDER BY date_time DESC LIMIT 1")
-            .bind(chat_id)
-            .fetch_one::<String>()
-            .await
-            .unwrap_or_default();
-
-        let name = clickhouse()
-            .query("SELECT first_name FROM chats_log WHERE chat_id = ? AND message_id = ? ORDER BY date_time DESC LIMIT 1")
-            .bind(chat_id)
-            .bind(message_id)
-            .fetch_one::<String>()
+    let Some(m) = stored else {
+        let chat_title = storage()
+            .chat_title(chat_id)
             .await
+            .ok()
+            .flatten()
             .unwrap_or_default();
-
-        (title, name)
+        let latest = latest.unwrap_or_default();
+        return MessageInfo {
+            message: latest.message,
+            chat_title,
+            first_name: String::new(),
+            sender_name: String::new(),
+            user_id: 0,
+            date_time: 0,
+            media: latest.media,
+            media_id: latest.media_id,
+            outgoing: false,
+        };
     };
 
-    MessageInfo { message, chat_title, first_name }
+    let sender_name = if m.second_name.is_empty() {
+        m.first_name.clone()
+    } else {
+        format!("{} {}", m.first_name, m.second_name)
+    };
+    let (message, media, media_id) = match latest {
+        Some(c) => (c.message, c.media, c.media_id),
+        None => (m.message, m.media, m.media_id),
+    };
+    MessageInfo {
+        message,
+        chat_title: m.chat_title,
+        first_name: m.first_name,
+        sender_name,
+        user_id: m.user_id,
+        date_time: m.date_time,
+        media,
+        media_id,
+        outgoing,
+    }
 }
 
-#[derive(Row, Serialize)]
+#[derive(Row, Serialize, Deserialize)]
 pub struct IncomingMessage {
     pub date_time: u32,
     pub message: String,
@@ -161,9 +563,39 @@ pub struct IncomingMessage {
     pub chat_usernames: Vec<String>,
     pub reply_to: u64,
     pub client_id: u64,
+    /// Raw TL as JSON: the update for live messages, the message for backfilled ones.
+    #[serde(default)]
+    pub raw: String,
+    /// Media description like "[photo]", empty without media.
+    #[serde(default)]
+    pub media: String,
+    /// Forum topic, 0 outside forums and in the General topic.
+    #[serde(default)]
+    pub topic_id: i32,
+    #[serde(default)]
+    pub topic_name: String,
+    /// Forward origin, see `handlers::extract::ForwardInfo`; zero when not forwarded.
+    #[serde(default)]
+    pub fwd_from_id: i64,
+    #[serde(default)]
+    pub fwd_from_name: String,
+    #[serde(default)]
+    pub fwd_message_id: i32,
+    #[serde(default)]
+    pub fwd_date: u32,
+    #[serde(default)]
+    pub fwd_saved_from_id: i64,
+    #[serde(default)]
+    pub fwd_saved_from_message_id: i32,
+    /// Shared by the messages of one album, 0 otherwise.
+    #[serde(default)]
+    pub grouped_id: i64,
+    /// Photo or document id, see `media_description::media_id`.
+    #[serde(default)]
+    pub media_id: i64,
 }
 
-#[derive(Row, Serialize)]
+#[derive(Row, Serialize, Deserialize)]
 pub struct OutgoingMessage {
     pub date_time: u32,
     pub message: String,
@@ -175,9 +607,34 @@ pub struct OutgoingMessage {
     pub reply_to: u64,
     pub raw: String,
     pub client_id: u64,
+    #[serde(default)]
+    pub media: String,
+    #[serde(default)]
+    pub topic_id: i32,
+    #[serde(default)]
+    pub topic_name: String,
+    /// Forward origin, see `handlers::extract::ForwardInfo`; zero when not forwarded.
+    #[serde(default)]
+    pub fwd_from_id: i64,
+    #[serde(default)]
+    pub fwd_from_name: String,
+    #[serde(default)]
+    pub fwd_message_id: i32,
+    #[serde(default)]
+    pub fwd_date: u32,
+    #[serde(default)]
+    pub fwd_saved_from_id: i64,
+    #[serde(default)]
+    pub fwd_saved_from_message_id: i32,
+    /// Shared by the messages of one album, 0 otherwise.
+    #[serde(default)]
+    pub grouped_id: i64,
+    /// Photo or document id, see `media_description::media_id`.
+    #[serde(default)]
+    pub media_id: i64,
 }
 
-#[derive(Row, Serialize)]
+#[derive(Row, Serialize, Deserialize)]
 pub struct EditedMessage {
     pub date_time: u32,
     pub chat_id: i64,
@@ -187,16 +644,131 @@ pub struct EditedMessage {
     pub diff: String,
     pub user_id: i64,
     pub client_id: u64,
+    /// Edit of one of our own messages.
+    #[serde(default)]
+    pub outgoing: bool,
+    /// The message was never seen before this edit; `original_message` and
+    /// `diff` are empty.
+    #[serde(default)]
+    pub original_unknown: bool,
+    /// Media before and after the edit, descriptions and ids as in chats_log.
+    #[serde(default)]
+    pub original_media: String,
+    #[serde(default)]
+    pub media: String,
+    #[serde(default)]
+    pub original_media_id: i64,
+    #[serde(default)]
+    pub media_id: i64,
 }
 
-#[derive(Row, Serialize)]
+/// A deletion with a snapshot of the message as last seen, so the row stands on
+/// its own once chats_log is cleaned up.
+#[derive(Row, Serialize, Deserialize)]
 pub struct DeletedMessage {
     pub date_time: u32,
     pub chat_id: i64,
     pub message_id: i64,
     pub client_id: u64,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub chat_title: String,
+    #[serde(default)]
+    pub user_id: u64,
+    #[serde(default)]
+    pub sender_name: String,
+    /// When the deleted message was sent, 0 if unknown.
+    #[serde(default)]
+    pub original_date: u32,
+    #[serde(default)]
+    pub media: String,
+    /// One of our own messages.
+    #[serde(default)]
+    pub outgoing: bool,
 }
 
+/// Latest reaction counts of a message, e.g. `["👍 3", "custom:5368324170671202286 1"]`.
+#[derive(Row, Serialize, Deserialize)]
+pub struct Reactions {
+    pub date_time: u32,
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub reactions: Vec<String>,
+    pub client_id: u64,
+}
+
+/// A downloaded photo or document, stored under its content hash.
+#[derive(Row, Serialize, Deserialize)]
+pub struct MediaFile {
+    pub date_time: u32,
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub sha256: String,
+    pub mime: String,
+    pub size: u64,
+    pub path: String,
+    pub client_id: u64,
+}
+
+/// Rows that feed the local message cache when pushed into a buffer.
+pub trait Indexed {
+    fn index(&self) {}
+}
+
+impl Indexed for IncomingMessage {
+    fn index(&self) {
+        crate::message_cache::insert(
+            self.chat_id,
+            self.message_id,
+            CachedMessage {
+                text: self.message.clone(),
+                chat_title: self.chat_title.clone(),
+                first_name: self.first_name.clone(),
+                second_name: self.second_name.clone(),
+                user_id: self.user_id,
+                date_time: self.date_time,
+                media: self.media.clone(),
+                media_id: self.media_id,
+                outgoing: false,
+            },
+        );
+    }
+}
+
+impl Indexed for OutgoingMessage {
+    fn index(&self) {
+        crate::message_cache::insert(
+            self.id,
+            self.message_id as i64,
+            CachedMessage {
+                text: self.message.clone(),
+                chat_title: self.title.clone(),
+                first_name: String::new(),
+                second_name: String::new(),
+                user_id: self.client_id,
+                date_time: self.date_time,
+                media: self.media.clone(),
+                media_id: self.media_id,
+                outgoing: true,
+            },
+        );
+    }
+}
+
+impl Indexed for EditedMessage {
+    fn index(&self) {
+        crate::message_cache::update_text(self.chat_id, self.message_id, &self.message);
+        crate::message_cache::update_media(self.chat_id, self.message_id, &self.media, self.media_id);
+    }
+}
+
+impl Indexed for DeletedMessage {}
+
+impl Indexed for Reactions {}
+
+impl Indexed for MediaFile {}
+
 #[derive(Row, Serialize)]
 pub struct JoinRequest {
     pub date_time: u32,
@@ -235,4 +807,146 @@ pub struct TelegramSession {
     pub date_active: u32,
     pub updated_at: u32,
     pub client_id: u64,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use clickhouse::Row;
+    use serde::Serialize;
+
+    use std::time::Duration;
+
+    use super::{BufferLimits, DeletedMessage, WriteBuffer, find_message, is_permanent};
+    use crate::storage::{Storage, StorageError, storage};
+
+    // Under test, `storage()` is the memory backend shared by all tests, so every
+    // test uses its own chat ids.
+
+    #[derive(Row, Serialize)]
+    struct Stored {
+        date_time: u32,
+        chat_id: i64,
+        message_id: i64,
+        message: String,
+        chat_title: String,
+        first_name: String,
+        second_name: String,
+        user_id: u64,
+        media: String,
+        media_id: i64,
+    }
+
+    fn stored(chat_id: i64, message_id: i64, message: &str) -> Stored {
+        Stored {
+            date_time: 1_700_000_000,
+            chat_id,
+            message_id,
+            message: message.to_string(),
+            chat_title: "chat".to_string(),
+            first_name: "Ann".to_string(),
+            second_name: "Lee".to_string(),
+            user_id: 7,
+            media: String::new(),
+            media_id: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn find_message_reads_storage_and_prefers_the_last_edit() {
+        storage()
+            .insert("chats_log", &[stored(-9101, 1, "hello")])
+            .await
+            .unwrap();
+        let mut edit = stored(-9101, 1, "hello, edited");
+        edit.media = "[photo]".to_string();
+        storage().insert("edited_log", &[edit]).await.unwrap();
+
+        let info = find_message(-9101, 1).await;
+        assert_eq!(info.message, "hello, edited");
+        assert_eq!(info.media, "[photo]");
+        assert_eq!(info.chat_title, "chat");
+        assert_eq!(info.sender_name, "Ann Lee");
+        assert_eq!(info.user_id, 7);
+        assert_eq!(info.date_time, 1_700_000_000);
+        assert!(!info.outgoing);
+    }
+
+    #[tokio::test]
+    async fn find_message_without_a_row_is_empty() {
+        let info = find_message(-9102, 1).await;
+        assert!(info.message.is_empty());
+        assert_eq!(info.date_time, 0);
+        assert!(!info.outgoing);
+    }
+
+    fn bad_response(msg: &str) -> StorageError {
+        clickhouse::error::Error::BadResponse(msg.to_string()).into()
+    }
+
+    #[test]
+    fn schema_rejections_are_permanent() {
+        assert!(is_permanent(&bad_response(
+            "Code: 16. DB::Exception: No such column media_id in table chats_log"
+        )));
+        assert!(is_permanent(&bad_response(
+            "  Code: 60. DB::Exception: Table default.media_log does not exist"
+        )));
+    }
+
+    #[test]
+    fn other_errors_are_retried() {
+        assert!(!is_permanent(&bad_response(
+            "Code: 241. DB::Exception: Memory limit exceeded"
+        )));
+        assert!(!is_permanent(&bad_response("502 Bad Gateway")));
+        assert!(!is_permanent(&bad_response("Code: 160")));
+        assert!(!is_permanent(&clickhouse::error::Error::RowNotFound.into()));
+        let json = serde_json::from_str::<u32>("x").unwrap_err();
+        assert!(!is_permanent(&json.into()));
+    }
+
+    #[tokio::test]
+    async fn push_past_the_cap_sheds_without_a_flusher() {
+        // Without a spool and with nothing flushing, pushing must not block.
+        static BUF: WriteBuffer<DeletedMessage> = WriteBuffer::new(
+            "test_cap",
+            BufferLimits {
+                max_rows: 5,
+                max_bytes: 1 << 20,
+                max_age: Duration::from_secs(3600),
+                cap_rows: 10,
+                cap_bytes: 1 << 20,
+            },
+        );
+        let push_all = async {
+            for message_id in 0..25 {
+                BUF.push(DeletedMessage {
+                    date_time: 0,
+                    chat_id: 1,
+                    message_id,
+                    client_id: 1,
+                    message: String::new(),
+                    chat_title: String::new(),
+                    user_id: 0,
+                    sender_name: String::new(),
+                    original_date: 0,
+                    media: String::new(),
+                    outgoing: false,
+                })
+                .await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), push_all)
+            .await
+            .expect("push blocked at the cap");
+
+        let pending = BUF.pending().await;
+        assert!(pending <= 10, "{pending} rows held");
+        // The newest rows are kept, the oldest went to the dead-letter file.
+        assert_eq!(BUF.find_last(|m| Some(m.message_id)).await, Some(24));
+        let path = "dead_letter/test_cap.dead.jsonl";
+        let dead = std::fs::read_to_string(path).unwrap();
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_dir("dead_letter");
+        assert_eq!(dead.lines().count(), 25 - pending);
+    }
+}
@@ -1,53 +1,147 @@
 use grammers_client::update::MessageDeletion;
-use log::info;
+use log::{debug, info};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::db::DeletedMessage;
+use crate::notifier::{Kind, Notice};
 use crate::utils::log_ignore::is_log_ignored;
 
 pub async fn save_deleted(
     deletion: &MessageDeletion,
     client_id: u64,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let channel_id = match deletion.channel_id() {
-        Some(id) => id,
-        None => return Ok(()),
-    };
+    record_deletions(deletion.channel_id(), deletion.messages(), client_id).await
+}
 
+/// Store deletions of `ids`; `channel_id` is only known for channels and supergroups.
+async fn record_deletions(
+    channel_id: Option<i64>,
+    ids: &[i32],
+    client_id: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)?
         .as_secs() as u32;
 
-    for &msg_id in deletion.messages() {
-        let info = crate::db::find_message(channel_id, msg_id as i64).await;
-        let chat_title = if info.chat_title.is_empty() {
-            channel_id.to_string()
+    for &msg_id in ids {
+        // Private chats and basic groups only report message ids, which are unique
+        // per account there. Without a known chat there is nothing worth storing.
+        let chat_id = match channel_id {
+            Some(id) => id,
+            None => match crate::message_cache::private_chat(msg_id as i64) {
+                Some(id) => id,
+                None => {
+                    debug!("deleted message {msg_id} skipped, its chat is unknown");
+                    continue;
+                }
+            },
+        };
+        let info = crate::db::find_message(chat_id, msg_id as i64).await;
+        let title_display = if !info.chat_title.is_empty() {
+            info.chat_title.clone()
         } else {
-            info.chat_title
+            chat_id.to_string()
         };
-        let sender_name = info.first_name;
-        let message = info.message;
-        let sender_short: String = sender_name.chars().take(10).collect();
 
-        if !is_log_ignored(channel_id) {
-            let title_short: String = chat_title.chars().take(25).collect();
+        if !is_log_ignored(chat_id) {
+            let title_short: String = title_display.chars().take(25).collect();
+            let sender_short: String = info.first_name.chars().take(10).collect();
+            let preview = match (info.media.is_empty(), info.message.is_empty()) {
+                (true, _) => info.message.clone(),
+                (false, true) => info.media.clone(),
+                (false, false) => format!("{} {}", info.media, info.message),
+            };
             info!(
                 "\x1b[91m{:<8} {:>8} {:<25} \x1b[90m│\x1b[91m {:<10} \x1b[90m│\x1b[91m {}\x1b[0m",
                 "deleted",
                 msg_id,
                 title_short,
                 sender_short,
-                message,
+                preview,
             );
         }
 
+        if !info.outgoing {
+            crate::notifier::notify(Notice {
+                kind: Kind::Deleted,
+                chat_id,
+                message_id: msg_id as i64,
+                chat_title: info.chat_title.clone(),
+                sender: info.sender_name.clone(),
+                body: match (info.media.is_empty(), info.message.is_empty()) {
+                    (true, _) => info.message.clone(),
+                    (false, true) => info.media.clone(),
+                    (false, false) => format!("{}\n{}", info.media, info.message),
+                },
+            });
+        }
+
         crate::db::DELETED_BUF.push(DeletedMessage {
             date_time: now,
-            chat_id: channel_id,
+            chat_id,
             message_id: msg_id as i64,
             client_id,
+            message: info.message,
+            chat_title: info.chat_title,
+            user_id: info.user_id,
+            sender_name: info.sender_name,
+            original_date: info.date_time,
+            media: info.media,
+            outgoing: info.outgoing,
         }).await;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::record_deletions;
+    use crate::db::DELETED_BUF;
+    use crate::message_cache::CachedMessage;
+
+    fn seen(chat_id: i64, message_id: i64, text: &str) {
+        crate::message_cache::insert(
+            chat_id,
+            message_id,
+            CachedMessage {
+                text: text.to_string(),
+                chat_title: "chat".to_string(),
+                first_name: "Ann".to_string(),
+                second_name: String::new(),
+                user_id: 7,
+                date_time: 1_700_000_000,
+                media: String::new(),
+                media_id: 0,
+                outgoing: false,
+            },
+        );
+    }
+
+    async fn deleted(chat_id: i64, message_id: i64) -> Option<(String, u32)> {
+        DELETED_BUF
+            .find_last(|d| {
+                (d.chat_id == chat_id && d.message_id == message_id)
+                    .then(|| (d.message.clone(), d.original_date))
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn channel_deletion_keeps_the_content() {
+        seen(9301, 5, "bye");
+        record_deletions(Some(9301), &[5], 1).await.unwrap();
+        assert_eq!(deleted(9301, 5).await, Some(("bye".to_string(), 1_700_000_000)));
+    }
+
+    #[tokio::test]
+    async fn private_deletion_resolves_the_chat_from_the_index() {
+        seen(9302, 930_201, "secret");
+        crate::message_cache::index_private(930_201, 9302);
+        record_deletions(None, &[930_201, 930_202], 1).await.unwrap();
+        assert_eq!(deleted(9302, 930_201).await, Some(("secret".to_string(), 1_700_000_000)));
+        // An unknown private message has no chat and is skipped.
+        let skipped = DELETED_BUF.find_last(|d| (d.message_id == 930_202).then_some(())).await;
+        assert!(skipped.is_none());
+    }
+}
@@ -2,63 +2,192 @@ use grammers_client::update::Message;
 use log::info;
 
 use crate::db::EditedMessage;
+use crate::message_cache::CachedMessage;
+use crate::notifier::{Kind, Notice};
 use crate::utils::log_ignore::is_log_ignored;
 
+/// What an edit update carries, as `record_edit` needs it.
+struct Edit {
+    chat_id: i64,
+    message_id: i64,
+    /// Formatted text with inline buttons appended.
+    text: String,
+    media: String,
+    media_id: i64,
+    user_id: i64,
+    chat_name: String,
+    sender_name: String,
+    date: i64,
+    edit_date: i64,
+    outgoing: bool,
+}
+
 pub async fn save_edited(
     message: &Message,
     client_id: u64,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let chat_id = message.peer_id().bare_id_unchecked();
-    let msg_id = message.id() as i64;
-    let mut message_content = crate::utils::format_entities::formatted_text(message);
+    let mut text = crate::utils::format_entities::formatted_text(message);
     if let Some(b) = crate::utils::inline_buttons::format_buttons(message) {
-        if !message_content.is_empty() {
-            message_content.push_str("\n\n");
+        if !text.is_empty() {
+            text.push_str("\n\n");
         }
-        message_content.push_str(&b);
+        text.push_str(&b);
     }
+    let edit = Edit {
+        chat_id: message.peer_id().bare_id_unchecked(),
+        message_id: message.id() as i64,
+        text,
+        media: crate::utils::media_description::describe(message).unwrap_or_default(),
+        media_id: crate::utils::media_description::media_id(message),
+        user_id: message
+            .sender()
+            .and_then(|s| s.id().bare_id())
+            .unwrap_or(0) as i64,
+        chat_name: message
+            .peer()
+            .map(|p| p.name().unwrap_or_default().to_string())
+            .unwrap_or_default(),
+        sender_name: message
+            .sender()
+            .and_then(|p| p.name().map(|s| s.to_string()))
+            .unwrap_or_default(),
+        date: message.date().timestamp(),
+        edit_date: message.edit_date().unwrap_or_else(|| message.date()).timestamp(),
+        outgoing: message.outgoing(),
+    };
+    record_edit(edit, client_id).await
+}
 
-    if message_content.is_empty() {
-        return Ok(());
+/// The caption of a stored message. Rows of media without a caption hold a
+/// fallback instead: the media description for our own messages, the raw TL
+/// JSON for others' stored before the fallback was dropped. An edit only
+/// carries the caption, so the fallback would always look changed.
+fn caption<'a>(message: &'a str, media: &str) -> &'a str {
+    let raw = message.starts_with("{\"")
+        && serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(message)
+            .is_ok_and(|m| m.len() == 1 && m.values().all(serde_json::Value::is_object));
+    if raw || (!media.is_empty() && message == media) {
+        ""
+    } else {
+        message
     }
+}
 
-    let original = crate::db::find_message(chat_id, msg_id).await.message;
+/// Compare an edit with the message as last seen, then log, notify and store it.
+async fn record_edit(edit: Edit, client_id: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let Edit {
+        chat_id,
+        message_id: msg_id,
+        text: message_content,
+        media,
+        media_id,
+        user_id,
+        chat_name,
+        sender_name,
+        date,
+        edit_date,
+        outgoing,
+    } = edit;
 
-    if original.is_empty() || original == message_content {
+    if message_content.is_empty() && media.is_empty() {
         return Ok(());
     }
 
-    let diff = unified_diff(&original, &message_content);
+    let info = crate::db::find_message(chat_id, msg_id).await;
+    // Sent before we started or missed while offline. The edit is stored anyway
+    // and becomes the baseline that later edits are compared against.
+    let original_unknown = info.message.is_empty() && info.date_time == 0 && info.media.is_empty();
+    let original = caption(&info.message, &info.media).to_string();
+    let text_changed = original != message_content;
+    // Rows stored before media ids were recorded only have the description.
+    let media_changed = !original_unknown
+        && (info.media != media || (info.media_id != 0 && info.media_id != media_id));
 
-    let user_id = message
-        .sender()
-        .and_then(|s| s.id().bare_id())
-        .unwrap_or(0) as i64;
+    if !original_unknown && !text_changed && !media_changed {
+        return Ok(());
+    }
 
-    let chat_name = message
-        .peer()
-        .map(|p| p.name().unwrap_or_default().to_string())
-        .unwrap_or_default();
+    let diff = if original_unknown || !text_changed {
+        String::new()
+    } else {
+        crate::utils::diff::unified_diff(&original, &message_content)
+    };
+    let media_line = match (info.media.is_empty(), media.is_empty()) {
+        _ if !media_changed => String::new(),
+        (true, _) => format!("media added: {media}"),
+        (false, true) => format!("media removed: {}", info.media),
+        _ if info.media == media => format!("media replaced: {media}"),
+        _ => format!("media replaced: {} → {media}", info.media),
+    };
 
-    let sender_name = message
-        .sender()
-        .and_then(|p| p.name().map(|s| s.to_string()))
-        .unwrap_or_default();
     let sender_short: String = sender_name.chars().take(10).collect();
 
     if !is_log_ignored(chat_id) {
         let chat_name_short: String = chat_name.chars().take(25).collect();
-        let colored = crate::utils::diff::colorize_unified_diff(&diff, &original, &message_content);
+        let colored = if original_unknown {
+            format!("(original unknown)\n{message_content}")
+        } else {
+            let mut colored = media_line.clone();
+            if text_changed {
+                if !colored.is_empty() {
+                    colored.push('\n');
+                }
+                colored.push_str(&crate::utils::diff::colorize_unified_diff(
+                    &diff,
+                    &original,
+                    &message_content,
+                ));
+            }
+            colored
+        };
         info!(
             "\x1b[93m{:<8} {:>8} {:<25} \x1b[90m│\x1b[93m {:<10}\x1b[0m\n{}",
             "edited",
-            message.id(),
+            msg_id,
             chat_name_short,
             sender_short,
             colored,
         );
     }
 
+    // Remember it, so a later deletion still shows the content.
+    if original_unknown {
+        crate::message_cache::insert_if_absent(
+            chat_id,
+            msg_id,
+            CachedMessage {
+                text: message_content.clone(),
+                chat_title: chat_name.clone(),
+                first_name: sender_name.clone(),
+                second_name: String::new(),
+                user_id: user_id as u64,
+                date_time: date as u32,
+                media: media.clone(),
+                media_id,
+                outgoing,
+            },
+        );
+    }
+
+    // Our own edits are recorded, but not worth a notification; neither are
+    // edits recovered by a catch-up.
+    if !outgoing && !crate::catch_up::is_missed(edit_date) {
+        crate::notifier::notify(Notice {
+            kind: Kind::Edited,
+            chat_id,
+            message_id: msg_id,
+            chat_title: chat_name,
+            sender: sender_name,
+            body: if original_unknown {
+                format!("(original unknown)\n{message_content}")
+            } else if media_line.is_empty() || diff.is_empty() {
+                format!("{media_line}{diff}")
+            } else {
+                format!("{media_line}\n{diff}")
+            },
+        });
+    }
+
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)?
         .as_secs() as u32;
@@ -72,15 +201,130 @@ pub async fn save_edited(
         diff,
         user_id,
         client_id,
+        outgoing,
+        original_unknown,
+        original_media: info.media,
+        media,
+        original_media_id: info.media_id,
+        media_id,
     }).await;
 
     Ok(())
 }
 
-fn unified_diff(original: &str, modified: &str) -> String {
-    similar::TextDiff::from_lines(original, modified)
-        .unified_diff()
-        .missing_newline_hint(false)
-        .to_string()
-}
+#[cfg(test)]
+mod tests {
+    use super::{Edit, caption, record_edit};
+    use crate::db::EDITED_BUF;
+    use crate::message_cache::CachedMessage;
+
+    fn edit(chat_id: i64, text: &str, media: &str) -> Edit {
+        Edit {
+            chat_id,
+            message_id: 1,
+            text: text.to_string(),
+            media: media.to_string(),
+            media_id: 0,
+            user_id: 7,
+            chat_name: "chat".to_string(),
+            sender_name: "Ann".to_string(),
+            date: 1_700_000_000,
+            edit_date: 1_700_000_100,
+            outgoing: false,
+        }
+    }
 
+    fn seen(chat_id: i64, text: &str) {
+        crate::message_cache::insert(
+            chat_id,
+            1,
+            CachedMessage {
+                text: text.to_string(),
+                chat_title: "chat".to_string(),
+                first_name: "Ann".to_string(),
+                second_name: String::new(),
+                user_id: 7,
+                date_time: 1_700_000_000,
+                media: String::new(),
+                media_id: 0,
+                outgoing: false,
+            },
+        );
+    }
+
+    /// (original, message, diff is empty, original_unknown) of the last buffered edit.
+    async fn last_edit(chat_id: i64) -> Option<(String, String, bool, bool)> {
+        EDITED_BUF
+            .find_last(|e| {
+                (e.chat_id == chat_id).then(|| {
+                    (
+                        e.original_message.clone(),
+                        e.message.clone(),
+                        e.diff.is_empty(),
+                        e.original_unknown,
+                    )
+                })
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn text_edit_is_stored_with_a_diff() {
+        seen(-9201, "hello");
+        record_edit(edit(-9201, "hello world", ""), 1).await.unwrap();
+        let (original, message, no_diff, unknown) = last_edit(-9201).await.unwrap();
+        assert_eq!(original, "hello");
+        assert_eq!(message, "hello world");
+        assert!(!no_diff);
+        assert!(!unknown);
+        // Pushing the edit updated the cache, so the next one diffs against it.
+        assert_eq!(crate::message_cache::get(-9201, 1).unwrap().text, "hello world");
+    }
+
+    #[tokio::test]
+    async fn unchanged_edit_is_not_stored() {
+        seen(-9202, "hello");
+        record_edit(edit(-9202, "hello", ""), 1).await.unwrap();
+        assert!(last_edit(-9202).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn media_edit_ignores_the_stored_fallback_text() {
+        for (chat_id, stored) in [(-9204, r#"{"Message":{"id":1,"message":""}}"#), (-9205, "[photo]")] {
+            seen(chat_id, stored);
+            let mut cached = crate::message_cache::get(chat_id, 1).unwrap();
+            cached.media = "[photo]".to_string();
+            crate::message_cache::insert(chat_id, 1, cached);
+
+            // Same photo, still no caption: nothing changed.
+            record_edit(edit(chat_id, "", "[photo]"), 1).await.unwrap();
+            assert!(last_edit(chat_id).await.is_none(), "{stored}");
+
+            // A caption added: the diff starts from an empty text.
+            record_edit(edit(chat_id, "look", "[photo]"), 1).await.unwrap();
+            let (original, message, _, _) = last_edit(chat_id).await.unwrap();
+            assert_eq!(original, "");
+            assert_eq!(message, "look");
+        }
+    }
+
+    #[test]
+    fn caption_drops_fallbacks_only() {
+        assert_eq!(caption(r#"{"Message":{"id":1}}"#, ""), "");
+        assert_eq!(caption("[photo]", "[photo]"), "");
+        assert_eq!(caption("[photo]", ""), "[photo]");
+        assert_eq!(caption(r#"{"a": 1}"#, ""), r#"{"a": 1}"#);
+        assert_eq!(caption("hello", "[photo]"), "hello");
+    }
+
+    #[tokio::test]
+    async fn edit_of_an_unseen_message_becomes_the_baseline() {
+        record_edit(edit(-9203, "first seen", ""), 1).await.unwrap();
+        let (original, message, no_diff, unknown) = last_edit(-9203).await.unwrap();
+        assert!(original.is_empty());
+        assert_eq!(message, "first seen");
+        assert!(no_diff);
+        assert!(unknown);
+        assert_eq!(crate::message_cache::get(-9203, 1).unwrap().text, "first seen");
+    }
+}
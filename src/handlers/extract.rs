@@ -2,6 +2,8 @@ use grammers_client::message::Message;
 use grammers_client::peer::Peer;
 use grammers_tl_types as tl;
 
+use crate::utils::peer::bot_api_id;
+
 pub struct SenderInfo {
     pub username: Vec<String>,
     pub first_name: String,
@@ -14,6 +16,33 @@ pub struct ChatInfo {
     pub chat_usernames: Vec<String>,
 }
 
+/// Where a forwarded message came from. Peers are Bot API ids, 0 when absent
+/// or hidden by the original sender's privacy settings.
+#[derive(Default)]
+pub struct ForwardInfo {
+    pub from_id: i64,
+    pub from_name: String,
+    pub message_id: i32,
+    pub date: u32,
+    pub saved_from_id: i64,
+    pub saved_from_message_id: i32,
+}
+
+impl ForwardInfo {
+    /// Console prefix like "[fwd from Some Channel]", empty if not forwarded.
+    pub fn preview(&self) -> String {
+        if self.date == 0 {
+            String::new()
+        } else if !self.from_name.is_empty() {
+            format!("[fwd from {}]", self.from_name)
+        } else if self.from_id != 0 {
+            format!("[fwd from {}]", self.from_id)
+        } else {
+            "[fwd]".to_string()
+        }
+    }
+}
+
 pub fn extract_community_tag_from_update(update: &tl::enums::Update) -> String {
     let msg = match update {
         tl::enums::Update::NewMessage(u) => &u.message,
@@ -30,6 +59,49 @@ pub fn extract_community_tag(msg: &tl::enums::Message) -> String {
     }
 }
 
+pub fn extract_forward_from_update(update: &tl::enums::Update) -> ForwardInfo {
+    let msg = match update {
+        tl::enums::Update::NewMessage(u) => &u.message,
+        tl::enums::Update::NewChannelMessage(u) => &u.message,
+        _ => return ForwardInfo::default(),
+    };
+    extract_forward(msg)
+}
+
+pub fn extract_forward(msg: &tl::enums::Message) -> ForwardInfo {
+    let Some(tl::enums::MessageFwdHeader::Header(h)) = (match msg {
+        tl::enums::Message::Message(m) => m.fwd_from.as_ref(),
+        _ => None,
+    }) else {
+        return ForwardInfo::default();
+    };
+    let bare = |p: &tl::enums::Peer| match p {
+        tl::enums::Peer::User(p) => p.user_id,
+        tl::enums::Peer::Chat(p) => p.chat_id,
+        tl::enums::Peer::Channel(p) => p.channel_id,
+    };
+    // from_name is only set when the sender hides their account; otherwise use
+    // the title we last saw for that chat, or the channel post's signature.
+    let from_name = h
+        .from_name
+        .clone()
+        .or_else(|| {
+            h.from_id
+                .as_ref()
+                .and_then(|p| crate::message_cache::chat_title(bare(p)))
+        })
+        .or_else(|| h.post_author.clone())
+        .unwrap_or_default();
+    ForwardInfo {
+        from_id: h.from_id.as_ref().map(bot_api_id).unwrap_or_default(),
+        from_name,
+        message_id: h.channel_post.unwrap_or_default(),
+        date: h.date as u32,
+        saved_from_id: h.saved_from_peer.as_ref().map(bot_api_id).unwrap_or_default(),
+        saved_from_message_id: h.saved_from_msg_id.unwrap_or_default(),
+    }
+}
+
 pub fn extract_sender(message: &Message) -> SenderInfo {
     match message.sender() {
         Some(Peer::User(user)) => SenderInfo {
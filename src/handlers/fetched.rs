@@ -0,0 +1,170 @@
+use grammers_client::Client;
+use grammers_client::message::Message;
+use grammers_client::peer::Peer;
+
+use crate::db::{IncomingMessage, OutgoingMessage};
+use crate::storage::{Storage, storage};
+use crate::utils::forum_topic;
+use super::extract::{extract_sender, extract_chat, extract_community_tag, extract_forward};
+
+/// Store a message fetched from Telegram rather than received as an update,
+/// normalised the same way as `save_incoming` / `save_outgoing`.
+pub async fn save_fetched(client: &Client, message: &Message, client_id: u64) {
+    crate::message_cache::index_if_private(message.peer_id(), message.id() as i64);
+    if message.outgoing() {
+        let row = outgoing_row(client, message, client_id).await;
+        crate::db::OUTGOING_BUF.push(row).await;
+    } else {
+        let row = incoming_row(client, message, client_id).await;
+        crate::db::INCOMING_BUF.push(row).await;
+    }
+}
+
+/// chats_log row for a fetched message.
+pub async fn incoming_row(client: &Client, message: &Message, client_id: u64) -> IncomingMessage {
+    let sender = extract_sender(message);
+    let chat = extract_chat(message);
+
+    let text = crate::utils::format_entities::formatted_text(message);
+    let raw = serde_json::to_string(&message.raw).unwrap_or_default();
+    let sender_bare_id = sender.user_id as i64;
+    let mut msg_content = if !text.is_empty() {
+        text
+    } else if let Some(action) = message.action() {
+        let sender_display = if sender.second_name.is_empty() {
+            sender.first_name.clone()
+        } else {
+            format!("{} {}", sender.first_name, sender.second_name)
+        };
+        crate::utils::service_action::format(action, Some(sender_bare_id), Some(&sender_display))
+    } else {
+        String::new()
+    };
+    if let Some(b) = crate::utils::inline_buttons::format_buttons_of_message(&message.raw) {
+        if !msg_content.is_empty() {
+            msg_content.push_str("\n\n");
+        }
+        msg_content.push_str(&b);
+    }
+
+    let reply_to = message.reply_to_message_id().unwrap_or(0) as u64;
+    let media = crate::utils::media_description::describe_message(&message.raw).unwrap_or_default();
+    let topic_id = forum_topic::topic_id(message);
+    let topic_name = forum_topic::topic_name(client, message, topic_id).await;
+    let forward = extract_forward(&message.raw);
+
+    IncomingMessage {
+        date_time: message.date().timestamp() as u32,
+        message: msg_content,
+        chat_title: chat.chat_title,
+        chat_id: message.peer_id().bare_id_unchecked(),
+        username: sender.username,
+        first_name: sender.first_name,
+        second_name: sender.second_name,
+        user_id: sender.user_id,
+        community_tag: extract_community_tag(&message.raw),
+        message_id: message.id() as i64,
+        chat_usernames: chat.chat_usernames,
+        reply_to,
+        client_id,
+        raw,
+        media,
+        topic_id,
+        topic_name,
+        fwd_from_id: forward.from_id,
+        fwd_from_name: forward.from_name,
+        fwd_message_id: forward.message_id,
+        fwd_date: forward.date,
+        fwd_saved_from_id: forward.saved_from_id,
+        fwd_saved_from_message_id: forward.saved_from_message_id,
+        grouped_id: message.grouped_id().unwrap_or_default(),
+        media_id: crate::utils::media_description::media_id_of_message(&message.raw),
+    }
+}
+
+/// telegram_messages_new row for one of our own fetched messages.
+pub async fn outgoing_row(client: &Client, message: &Message, client_id: u64) -> OutgoingMessage {
+    let (title, usernames) = super::outgoing::chat_names(message);
+
+    let text = crate::utils::format_entities::formatted_text(message);
+    let media_desc = crate::utils::media_description::describe_message(&message.raw);
+    let mut msg_content = if !text.is_empty() {
+        text
+    } else if let Some(action) = message.action() {
+        let sender_id = message.sender_id().map(|p| p.bare_id_unchecked());
+        let sender_name = message.sender().map(|p| match p {
+            Peer::User(u) => u.full_name(),
+            _ => p.name().unwrap_or_default().to_string(),
+        });
+        crate::utils::service_action::format(action, sender_id, sender_name.as_deref())
+    } else {
+        media_desc.clone().unwrap_or_default()
+    };
+    if let Some(b) = crate::utils::inline_buttons::format_buttons_of_message(&message.raw) {
+        if !msg_content.is_empty() {
+            msg_content.push_str("\n\n");
+        }
+        msg_content.push_str(&b);
+    }
+
+    let topic_id = forum_topic::topic_id(message);
+    let topic_name = forum_topic::topic_name(client, message, topic_id).await;
+    let forward = extract_forward(&message.raw);
+
+    OutgoingMessage {
+        date_time: message.date().timestamp() as u32,
+        message: msg_content,
+        title,
+        id: message.peer_id().bare_id_unchecked(),
+        admins2: Vec::new(),
+        usernames,
+        message_id: message.id() as u64,
+        reply_to: message.reply_to_message_id().unwrap_or(0) as u64,
+        raw: serde_json::to_string(&message.raw).unwrap_or_default(),
+        client_id,
+        media: media_desc.unwrap_or_default(),
+        topic_id,
+        topic_name,
+        fwd_from_id: forward.from_id,
+        fwd_from_name: forward.from_name,
+        fwd_message_id: forward.message_id,
+        fwd_date: forward.date,
+        fwd_saved_from_id: forward.saved_from_id,
+        fwd_saved_from_message_id: forward.saved_from_message_id,
+        grouped_id: message.grouped_id().unwrap_or_default(),
+        media_id: crate::utils::media_description::media_id_of_message(&message.raw),
+    }
+}
+
+/// Whether the message is already cached, buffered or stored, as incoming or outgoing.
+pub async fn message_exists(chat_id: i64, message_id: i32) -> bool {
+    if crate::message_cache::contains(chat_id, message_id as i64) {
+        return true;
+    }
+    // Check unflushed incoming and outgoing buffers
+    let in_buf = crate::db::INCOMING_BUF
+        .find_last(|m| {
+            if m.chat_id == chat_id && m.message_id == message_id as i64 {
+                Some(())
+            } else {
+                None
+            }
+        })
+        .await
+        .is_some();
+    if in_buf {
+        return true;
+    }
+    let out_buf = crate::db::OUTGOING_BUF
+        .find_last(|m| (m.id == chat_id && m.message_id == message_id as u64).then_some(()))
+        .await
+        .is_some();
+    if out_buf {
+        return true;
+    }
+
+    storage()
+        .message_exists(chat_id, message_id as i64)
+        .await
+        .unwrap_or(false)
+}
@@ -1,12 +1,16 @@
+use grammers_client::Client;
 use grammers_client::update::Message;
 use log::info;
 
 use crate::db::IncomingMessage;
+use crate::utils::album::{self, AlbumItem};
+use crate::utils::forum_topic;
 use crate::utils::log_ignore::is_log_ignored;
-use super::extract::{extract_sender, extract_chat, extract_community_tag_from_update};
+use super::extract::{extract_sender, extract_chat, extract_community_tag_from_update, extract_forward_from_update};
 
-pub async fn save_incoming(message: &Message, client_id: u64) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn save_incoming(client: &Client, message: &Message, client_id: u64) -> Result<(), Box<dyn std::error::Error>> {
     let media_desc = crate::utils::media_description::describe(message);
+    let forward = extract_forward_from_update(&message.raw);
 
     let sender = extract_sender(message);
     let chat = extract_chat(message);
@@ -45,29 +49,49 @@ pub async fn save_incoming(message: &Message, client_id: u64) -> Result<(), Box<
             }
             preview.push_str(b);
         }
+        let fwd_preview = forward.preview();
+        if !fwd_preview.is_empty() {
+            preview = format!("{fwd_preview} {preview}");
+        }
         let sender_short: String = sender_display.chars().take(10).collect();
         let chat_name_short: String = chat.chat_title.chars().take(25).collect();
 
-        let reply_line = crate::utils::reply_preview::format_reply_line(message).await;
-        if !reply_line.is_empty() {
-            info!("{}", reply_line);
+        if let Some(grouped_id) = message.grouped_id() {
+            album::add(AlbumItem {
+                label: "incoming",
+                color: 92,
+                chat_id,
+                grouped_id,
+                message_id: message.id(),
+                chat: chat_name_short,
+                sender: sender_short,
+                media: media_desc.clone().unwrap_or_default(),
+                caption: text.to_string(),
+                prefix: fwd_preview,
+            });
+        } else {
+            let reply_line = crate::utils::reply_preview::format_reply_line(message).await;
+            if !reply_line.is_empty() {
+                info!("{}", reply_line);
+            }
+            info!(
+                "\x1b[92m{:<8} {:>8} {:<25} \x1b[90m│\x1b[92m {:<10} \x1b[90m│\x1b[92m {}\x1b[0m",
+                "incoming", message.id(), chat_name_short, sender_short, &preview
+            );
         }
-        info!(
-            "\x1b[92m{:<8} {:>8} {:<25} \x1b[90m│\x1b[92m {:<10} \x1b[90m│\x1b[92m {}\x1b[0m",
-            "incoming", message.id(), chat_name_short, sender_short, &preview
-        );
     }
 
     let text = crate::utils::format_entities::formatted_text(message);
+    let raw = serde_json::to_string(&message.raw).unwrap_or_default();
     let sender_bare_id = sender.user_id as i64;
-    let mut msg_content = if text.is_empty() {
-        if let Some(action) = message.action() {
-            crate::utils::service_action::format(action, Some(sender_bare_id), Some(&sender_display))
-        } else {
-            serde_json::to_string(&message.raw).unwrap_or_default()
-        }
-    } else {
+    // Media without a caption leaves the text empty; the media is in `media`,
+    // the full message in `raw`.
+    let mut msg_content = if !text.is_empty() {
         text.to_string()
+    } else if let Some(action) = message.action() {
+        crate::utils::service_action::format(action, Some(sender_bare_id), Some(&sender_display))
+    } else {
+        String::new()
     };
     if let Some(b) = &buttons {
         if !msg_content.is_empty() {
@@ -77,7 +101,10 @@ pub async fn save_incoming(message: &Message, client_id: u64) -> Result<(), Box<
     }
 
     let reply_to = message.reply_to_message_id().unwrap_or(0) as u64;
+    let topic_id = forum_topic::topic_id(message);
+    let topic_name = forum_topic::topic_name(client, message, topic_id).await;
 
+    crate::message_cache::index_if_private(message.peer_id(), message.id() as i64);
     crate::db::INCOMING_BUF.push(IncomingMessage {
         date_time: message.date().timestamp() as u32,
         message: msg_content,
@@ -92,6 +119,18 @@ pub async fn save_incoming(message: &Message, client_id: u64) -> Result<(), Box<
         chat_usernames: chat.chat_usernames,
         reply_to,
         client_id,
+        raw,
+        media: media_desc.unwrap_or_default(),
+        topic_id,
+        topic_name,
+        fwd_from_id: forward.from_id,
+        fwd_from_name: forward.from_name,
+        fwd_message_id: forward.message_id,
+        fwd_date: forward.date,
+        fwd_saved_from_id: forward.saved_from_id,
+        fwd_saved_from_message_id: forward.saved_from_message_id,
+        grouped_id: message.grouped_id().unwrap_or_default(),
+        media_id: crate::utils::media_description::media_id(message),
     }).await;
 
     Ok(())
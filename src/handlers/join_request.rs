@@ -4,7 +4,8 @@ use std::collections::HashSet;
 use std::sync::LazyLock;
 use tokio::sync::Mutex;
 
-use crate::db::{JoinRequest, clickhouse};
+use crate::db::JoinRequest;
+use crate::storage::{Storage, storage};
 
 static SEEN: LazyLock<Mutex<HashSet<(i64, u64)>>> =
     LazyLock::new(|| Mutex::new(HashSet::new()));
@@ -45,11 +46,7 @@ pub async fn handle_pending_join_requests(
         return Ok(());
     }
 
-    let mut insert = clickhouse().insert::<JoinRequest>("join_requests_log").await?;
-    for row in &rows {
-        insert.write(row).await?;
-    }
-    insert.end().await?;
+    storage().insert("join_requests_log", &rows).await?;
 
     Ok(())
 }
@@ -1,17 +1,20 @@
-mod auto_cat;
 mod backfill_reply;
 mod deleted;
 mod edited;
 mod extract;
+mod fetched;
 mod incoming;
 mod join_request;
 mod outgoing;
+mod reactions;
+mod rules;
 
-pub use auto_cat::handle_auto_cat;
 pub use backfill_reply::backfill_reply;
 pub use deleted::save_deleted;
 pub use edited::save_edited;
+pub use fetched::{message_exists, save_fetched};
 pub use incoming::save_incoming;
 pub use join_request::handle_pending_join_requests;
 pub use outgoing::save_outgoing;
-
+pub use reactions::{save_message_reactions, save_reactions};
+pub use rules::{handle_rules, load_rules};
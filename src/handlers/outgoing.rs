@@ -1,61 +1,31 @@
-use clickhouse::Row;
+use grammers_client::Client;
 use grammers_client::peer::Peer;
 use grammers_client::update::Message;
 use log::info;
-use serde::Deserialize;
 
 use crate::db::OutgoingMessage;
+use crate::storage::{Storage, storage};
+use crate::utils::album::{self, AlbumItem};
+use crate::utils::forum_topic;
+use super::extract::extract_forward_from_update;
 
-#[derive(Row, Deserialize)]
-struct LastChatRow {
-    title: String,
-    usernames: Vec<String>,
-}
-
-pub async fn save_outgoing(message: &Message, client_id: u64) -> Result<(), Box<dyn std::error::Error>> {
-    let (title, usernames) = match message.peer() {
-        Some(Peer::User(user)) => {
-            let name = match (user.first_name(), user.last_name()) {
-                (Some(first), Some(last)) if !last.is_empty() => format!("{} {}", first, last),
-                (Some(first), _) => first.to_string(),
-                _ => user.username().unwrap_or_default().to_string(),
-            };
-            (
-                name,
-                user.username().map(|u| vec![u.to_string()]).unwrap_or_default(),
-            )
-        },
-        Some(Peer::Group(group)) => (
-            group.title().unwrap_or_default().to_string(),
-            group.usernames().into_iter().map(|s| s.to_string()).collect(),
-        ),
-        Some(Peer::Channel(channel)) => (
-            channel.title().to_string(),
-            channel.usernames().into_iter().map(|s| s.to_string()).collect(),
-        ),
-        Some(Peer::Community(community)) => (community.title().to_string(), Vec::new()),
-        None => (String::new(), Vec::new()),
-    };
-
-    let title = if title.is_empty() {
-        message.peer()
-            .and_then(|p| p.name().map(|s| s.to_string()))
-            .unwrap_or_default()
-    } else {
-        title
-    };
-
+pub async fn save_outgoing(client: &Client, message: &Message, client_id: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let (title, usernames) = chat_names(message);
     let chat_id = message.peer_id().bare_id_unchecked();
 
     let (title, usernames) = if title.is_empty() {
-        match crate::db::clickhouse()
-            .query("SELECT title, usernames FROM telegram_messages_new WHERE id = ? AND title != '' ORDER BY date_time DESC LIMIT 1")
-            .bind(chat_id)
-            .fetch_one::<LastChatRow>()
-            .await
-        {
-            Ok(row) => (row.title, row.usernames),
-            Err(_) => (title, usernames),
+        let buffered = crate::db::OUTGOING_BUF
+            .find_last(|m| {
+                (m.id == chat_id && !m.title.is_empty())
+                    .then(|| (m.title.clone(), m.usernames.clone()))
+            })
+            .await;
+        match buffered {
+            Some(found) => found,
+            None => match storage().outgoing_chat(chat_id).await {
+                Ok(Some(found)) => found,
+                _ => (title, usernames),
+            },
         }
     } else {
         (title, usernames)
@@ -64,10 +34,13 @@ pub async fn save_outgoing(message: &Message, client_id: u64) -> Result<(), Box<
     let text = crate::utils::format_entities::formatted_text(message);
     let raw = serde_json::to_string(&message.raw).unwrap_or_default();
     let reply_to = message.reply_to_message_id().unwrap_or(0) as u64;
+    let topic_id = forum_topic::topic_id(message);
+    let topic_name = forum_topic::topic_name(client, message, topic_id).await;
 
     let admins: Vec<String> = Vec::new();
 
     let media_desc = crate::utils::media_description::describe(message);
+    let forward = extract_forward_from_update(&message.raw);
     let buttons = crate::utils::inline_buttons::format_buttons(message);
     let sender_id = message.sender_id().map(|p| p.bare_id_unchecked());
     let sender_name = message.sender().map(|p| match p {
@@ -96,17 +69,36 @@ pub async fn save_outgoing(message: &Message, client_id: u64) -> Result<(), Box<
         }
         preview.push_str(b);
     }
+    let fwd_preview = forward.preview();
+    if !fwd_preview.is_empty() {
+        preview = format!("{fwd_preview} {preview}");
+    }
 
     {
         let title_short: String = title.chars().take(25).collect();
-        let reply_line = crate::utils::reply_preview::format_reply_line(message).await;
-        if !reply_line.is_empty() {
-            info!("{}", reply_line);
+        if let Some(grouped_id) = message.grouped_id() {
+            album::add(AlbumItem {
+                label: "outgoing",
+                color: 95,
+                chat_id,
+                grouped_id,
+                message_id: message.id(),
+                chat: title_short,
+                sender: String::new(),
+                media: media_desc.clone().unwrap_or_default(),
+                caption: text.clone(),
+                prefix: fwd_preview,
+            });
+        } else {
+            let reply_line = crate::utils::reply_preview::format_reply_line(message).await;
+            if !reply_line.is_empty() {
+                info!("{}", reply_line);
+            }
+            info!(
+                "\x1b[95m{:<8} {:>8} {:<25} \x1b[90m│\x1b[95m {:<10} \x1b[90m│\x1b[95m {}\x1b[0m",
+                "outgoing", message.id(), title_short, "", &preview
+            );
         }
-        info!(
-            "\x1b[95m{:<8} {:>8} {:<25} \x1b[90m│\x1b[95m {:<10} \x1b[90m│\x1b[95m {}\x1b[0m",
-            "outgoing", message.id(), title_short, "", &preview
-        );
     }
 
     let mut msg_content = if !text.is_empty() {
@@ -114,7 +106,7 @@ pub async fn save_outgoing(message: &Message, client_id: u64) -> Result<(), Box<
     } else if let Some(ref desc) = action_desc {
         desc.clone()
     } else {
-        media_desc.unwrap_or_default()
+        media_desc.clone().unwrap_or_default()
     };
     if let Some(b) = &buttons {
         if !msg_content.is_empty() {
@@ -123,8 +115,8 @@ pub async fn save_outgoing(message: &Message, client_id: u64) -> Result<(), Box<
         msg_content.push_str(b);
     }
 
-    let mut insert = crate::db::clickhouse().insert::<OutgoingMessage>("telegram_messages_new").await?;
-    insert.write(&OutgoingMessage {
+    crate::message_cache::index_if_private(message.peer_id(), message.id() as i64);
+    crate::db::OUTGOING_BUF.push(OutgoingMessage {
         date_time: message.date().timestamp() as u32,
         message: msg_content,
         title,
@@ -135,8 +127,54 @@ pub async fn save_outgoing(message: &Message, client_id: u64) -> Result<(), Box<
         reply_to,
         raw,
         client_id,
-    }).await?;
-    insert.end().await?;
+        media: media_desc.unwrap_or_default(),
+        topic_id,
+        topic_name,
+        fwd_from_id: forward.from_id,
+        fwd_from_name: forward.from_name,
+        fwd_message_id: forward.message_id,
+        fwd_date: forward.date,
+        fwd_saved_from_id: forward.saved_from_id,
+        fwd_saved_from_message_id: forward.saved_from_message_id,
+        grouped_id: message.grouped_id().unwrap_or_default(),
+        media_id: crate::utils::media_description::media_id(message),
+    }).await;
 
     Ok(())
 }
+
+/// Title and usernames of the chat of one of our own messages.
+pub(super) fn chat_names(message: &grammers_client::message::Message) -> (String, Vec<String>) {
+    let (title, usernames) = match message.peer() {
+        Some(Peer::User(user)) => {
+            let name = match (user.first_name(), user.last_name()) {
+                (Some(first), Some(last)) if !last.is_empty() => format!("{} {}", first, last),
+                (Some(first), _) => first.to_string(),
+                _ => user.username().unwrap_or_default().to_string(),
+            };
+            (
+                name,
+                user.username().map(|u| vec![u.to_string()]).unwrap_or_default(),
+            )
+        },
+        Some(Peer::Group(group)) => (
+            group.title().unwrap_or_default().to_string(),
+            group.usernames().into_iter().map(|s| s.to_string()).collect(),
+        ),
+        Some(Peer::Channel(channel)) => (
+            channel.title().to_string(),
+            channel.usernames().into_iter().map(|s| s.to_string()).collect(),
+        ),
+        Some(Peer::Community(community)) => (community.title().to_string(), Vec::new()),
+        None => (String::new(), Vec::new()),
+    };
+
+    let title = if title.is_empty() {
+        message.peer()
+            .and_then(|p| p.name().map(|s| s.to_string()))
+            .unwrap_or_default()
+    } else {
+        title
+    };
+    (title, usernames)
+}
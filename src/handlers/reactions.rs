@@ -0,0 +1,120 @@
+use grammers_client::update::Message;
+use grammers_tl_types as tl;
+use log::info;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use tokio::sync::Mutex;
+
+use crate::db::Reactions;
+use crate::utils::log_ignore::is_log_ignored;
+
+/// Last reaction list written per message, so edits that don't touch reactions
+/// (and repeated updates) don't produce duplicate rows.
+static LAST_SEEN: LazyLock<Mutex<HashMap<(i64, i64), Vec<String>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+const LAST_SEEN_MAX: usize = 50_000;
+
+/// `UpdateMessageReactions`: sent for messages in groups and channels, including ours.
+pub async fn save_reactions(
+    update: &tl::types::UpdateMessageReactions,
+    client_id: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let chat_id = match &update.peer {
+        tl::enums::Peer::User(p) => p.user_id,
+        tl::enums::Peer::Chat(p) => p.chat_id,
+        tl::enums::Peer::Channel(p) => p.channel_id,
+    };
+    record(chat_id, update.msg_id as i64, &update.reactions, client_id).await;
+    Ok(())
+}
+
+/// Reactions carried by an edited message: private chats and basic groups report
+/// reaction changes as message edits.
+pub async fn save_message_reactions(
+    message: &Message,
+    client_id: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(reactions) = extract_reactions_from_update(&message.raw) else {
+        return Ok(());
+    };
+    let chat_id = message.peer_id().bare_id_unchecked();
+    record(chat_id, message.id() as i64, reactions, client_id).await;
+    Ok(())
+}
+
+fn extract_reactions_from_update(update: &tl::enums::Update) -> Option<&tl::enums::MessageReactions> {
+    let msg = match update {
+        tl::enums::Update::EditMessage(u) => &u.message,
+        tl::enums::Update::EditChannelMessage(u) => &u.message,
+        _ => return None,
+    };
+    match msg {
+        tl::enums::Message::Message(m) => m.reactions.as_ref(),
+        _ => None,
+    }
+}
+
+/// Current counts as "<reaction> <count>", e.g. "👍 3", "custom:5368324170671202286 1", "paid 50".
+fn format_reactions(reactions: &tl::enums::MessageReactions) -> Vec<String> {
+    let tl::enums::MessageReactions::Reactions(r) = reactions;
+    r.results
+        .iter()
+        .filter_map(|rc| {
+            let tl::enums::ReactionCount::Count(rc) = rc;
+            let reaction = match &rc.reaction {
+                tl::enums::Reaction::Empty => return None,
+                tl::enums::Reaction::Emoji(e) => e.emoticon.clone(),
+                tl::enums::Reaction::CustomEmoji(c) => format!("custom:{}", c.document_id),
+                tl::enums::Reaction::Paid => "paid".to_string(),
+            };
+            Some(format!("{reaction} {}", rc.count))
+        })
+        .collect()
+}
+
+async fn record(
+    chat_id: i64,
+    message_id: i64,
+    reactions: &tl::enums::MessageReactions,
+    client_id: u64,
+) {
+    let reactions = format_reactions(reactions);
+    {
+        let mut seen = LAST_SEEN.lock().await;
+        match seen.get(&(chat_id, message_id)) {
+            Some(last) if *last == reactions => return,
+            // Nothing recorded and nothing to record.
+            None if reactions.is_empty() => return,
+            _ => {}
+        }
+        if seen.len() >= LAST_SEEN_MAX {
+            seen.clear();
+        }
+        seen.insert((chat_id, message_id), reactions.clone());
+    }
+
+    if !is_log_ignored(chat_id) {
+        let title = crate::message_cache::get(chat_id, message_id)
+            .map(|m| m.chat_title)
+            .or_else(|| crate::message_cache::chat_title(chat_id))
+            .unwrap_or_else(|| chat_id.to_string());
+        let title_short: String = title.chars().take(25).collect();
+        let shown = if reactions.is_empty() {
+            "[none]".to_string()
+        } else {
+            reactions.join(", ")
+        };
+        info!(
+            "\x1b[95m{:<8} {:>8} {:<25} \x1b[90m│\x1b[95m {}\x1b[0m",
+            "reaction", message_id, title_short, shown,
+        );
+    }
+
+    crate::db::REACTIONS_BUF.push(Reactions {
+        date_time: chrono::Utc::now().timestamp() as u32,
+        chat_id,
+        message_id,
+        reactions,
+        client_id,
+    }).await;
+}
@@ -0,0 +1,300 @@
+use grammers_client::Client;
+use grammers_client::update::Message;
+use grammers_tl_types as tl;
+use log::{error, info, warn};
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Automations loaded from the JSON file named by `RULES_FILE`; none if unset.
+///
+/// Every new message is checked against each rule. All conditions that are set
+/// must hold; a matching rule runs its actions in order, in the background.
+/// Chat and sender ids are Bot API ids everywhere: users positive, basic groups
+/// negative, channels and supergroups `-100…`. See `rules.example.json`.
+static RULES: LazyLock<Vec<Rule>> = LazyLock::new(|| {
+    let Ok(path) = std::env::var("RULES_FILE") else {
+        return Vec::new();
+    };
+    let json = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("cannot read RULES_FILE {path}: {e}"));
+    let rules: Vec<Rule> =
+        serde_json::from_str(&json).unwrap_or_else(|e| panic!("RULES_FILE {path} invalid: {e}"));
+    for rule in &rules {
+        for action in &rule.actions {
+            if !(0.0..=MAX_DELAY_SECS).contains(&action.delay_secs) {
+                panic!(
+                    "RULES_FILE {path} invalid: rule {} has delay_secs {}, expected 0 to {MAX_DELAY_SECS}",
+                    rule.name, action.delay_secs
+                );
+            }
+        }
+    }
+    rules
+});
+
+/// Longest `delay_secs` a rule may use: one day.
+const MAX_DELAY_SECS: f64 = 86_400.0;
+
+#[derive(Deserialize)]
+struct Rule {
+    name: String,
+    #[serde(rename = "match", default)]
+    conditions: Conditions,
+    actions: Vec<Action>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Conditions {
+    /// Bot API chat ids.
+    #[serde(default)]
+    chats: Vec<i64>,
+    /// Bot API sender ids.
+    #[serde(default)]
+    senders: Vec<i64>,
+    /// Searched for in the message text.
+    #[serde(default, deserialize_with = "regex")]
+    regex: Option<Regex>,
+    /// Media kinds as the console shows them, matched by prefix: "photo",
+    /// "sticker", "voice", "video", "file", ...
+    #[serde(default)]
+    media: Vec<String>,
+    /// Only our own messages, or only others'; `null` matches both. Defaults to
+    /// others' only, so a rule can't trigger on what its own actions send.
+    #[serde(default = "others_only")]
+    outgoing: Option<bool>,
+}
+
+fn others_only() -> Option<bool> {
+    Some(false)
+}
+
+impl Default for Conditions {
+    fn default() -> Self {
+        Self {
+            chats: Vec::new(),
+            senders: Vec::new(),
+            regex: None,
+            media: Vec::new(),
+            outgoing: others_only(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Action {
+    /// Wait this long before running the action, at most `MAX_DELAY_SECS`.
+    #[serde(default)]
+    delay_secs: f64,
+    #[serde(flatten)]
+    kind: ActionKind,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ActionKind {
+    /// Reply to the message.
+    Reply { text: String },
+    /// Send a message to `chat` (Bot API id), or to the message's chat.
+    Send { text: String, chat: Option<i64> },
+    /// Delete the message, or with `"target": "sent"` what the rule last sent.
+    Delete {
+        #[serde(default)]
+        target: Target,
+    },
+    /// Forward the message to `to` (Bot API id).
+    Forward { to: i64 },
+    React { emoji: String },
+    MarkRead,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+enum Target {
+    #[default]
+    Trigger,
+    Sent,
+}
+
+fn regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Regex>, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Load the rules file at startup, so a broken one fails early.
+pub fn load_rules() {
+    if std::env::var_os("RULES_FILE").is_none() {
+        warn!("RULES_FILE not set, no rules loaded (the #грбн auto-reply is a rule now, see rules.example.json)");
+    } else if !RULES.is_empty() {
+        info!("loaded {} rules", RULES.len());
+    }
+}
+
+pub async fn handle_rules(client: &Client, message: &Message) -> crate::Result<()> {
+    for rule in RULES.iter() {
+        if !rule.conditions.matches(message) {
+            continue;
+        }
+        info!(
+            "\x1b[96m{:<8} {:>8} {:<25}\x1b[0m",
+            "rule", message.id(), rule.name
+        );
+        let client = client.clone();
+        let trigger = grammers_client::message::Message::clone(message);
+        tokio::spawn(async move {
+            if let Err(e) = run(&client, &trigger, rule).await {
+                error!("rule {} failed: {e}", rule.name);
+            }
+        });
+    }
+    Ok(())
+}
+
+impl Conditions {
+    fn matches(&self, message: &Message) -> bool {
+        if !self.chats.is_empty() && !self.chats.contains(&message.peer_id().bot_api_dialog_id_unchecked()) {
+            return false;
+        }
+        if !self.senders.is_empty() {
+            let sender = message.sender().map(|s| s.id().bot_api_dialog_id_unchecked());
+            if !sender.is_some_and(|id| self.senders.contains(&id)) {
+                return false;
+            }
+        }
+        if self.outgoing.is_some_and(|o| o != message.outgoing()) {
+            return false;
+        }
+        if self.regex.as_ref().is_some_and(|re| !re.is_match(message.text())) {
+            return false;
+        }
+        if !self.media.is_empty() {
+            let Some(desc) = crate::utils::media_description::describe(message) else {
+                return false;
+            };
+            let desc = desc.trim_start_matches('[');
+            if !self.media.iter().any(|kind| desc.starts_with(kind.as_str())) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+async fn run(
+    client: &Client,
+    trigger: &grammers_client::message::Message,
+    rule: &Rule,
+) -> Result<(), Error> {
+    // The message most recently sent by this rule, for `"target": "sent"`.
+    let mut sent: Option<grammers_client::message::Message> = None;
+    for action in &rule.actions {
+        if action.delay_secs > 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(action.delay_secs)).await;
+        }
+        perform(client, trigger, &action.kind, &mut sent)
+            .await
+            .map_err(|e| format!("{:?}: {e}", action.kind))?;
+    }
+    Ok(())
+}
+
+async fn perform(
+    client: &Client,
+    trigger: &grammers_client::message::Message,
+    action: &ActionKind,
+    sent: &mut Option<grammers_client::message::Message>,
+) -> Result<(), Error> {
+    match action {
+        ActionKind::Reply { text } => {
+            *sent = Some(trigger.reply(text.as_str()).await?);
+        }
+        ActionKind::Send { text, chat } => {
+            let peer = match chat {
+                Some(id) => crate::utils::peer::resolve(client, *id).await?,
+                None => chat_ref(trigger).await?,
+            };
+            *sent = Some(client.send_message(peer, text.as_str()).await?);
+        }
+        ActionKind::Delete { target: Target::Trigger } => {
+            trigger.delete().await?;
+        }
+        ActionKind::Delete { target: Target::Sent } => {
+            if let Some(message) = sent.take() {
+                message.delete().await?;
+            }
+        }
+        ActionKind::Forward { to } => {
+            let destination = crate::utils::peer::resolve(client, *to).await?;
+            let source = chat_ref(trigger).await?;
+            client
+                .forward_messages(destination, &[trigger.id()], source)
+                .await?;
+        }
+        ActionKind::React { emoji } => {
+            let peer = chat_ref(trigger).await?;
+            client
+                .invoke(&tl::functions::messages::SendReaction {
+                    big: false,
+                    add_to_recent: false,
+                    peer: peer.into(),
+                    msg_id: trigger.id(),
+                    reaction: Some(vec![
+                        tl::types::ReactionEmoji {
+                            emoticon: emoji.clone(),
+                        }
+                        .into(),
+                    ]),
+                })
+                .await?;
+        }
+        ActionKind::MarkRead => {
+            client.mark_as_read(chat_ref(trigger).await?).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn chat_ref(
+    message: &grammers_client::message::Message,
+) -> Result<grammers_session::types::PeerRef, Error> {
+    let peer = message.peer().ok_or("chat of the message is unknown")?;
+    peer.to_ref()
+        .await?
+        .ok_or_else(|| "no access hash for the message's chat".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rule;
+
+    fn outgoing(json: &str) -> Option<bool> {
+        serde_json::from_str::<Rule>(json).unwrap().conditions.outgoing
+    }
+
+    #[test]
+    fn rules_only_match_others_messages_by_default() {
+        assert_eq!(outgoing(r#"{"name": "a", "actions": []}"#), Some(false));
+        assert_eq!(outgoing(r#"{"name": "a", "match": {}, "actions": []}"#), Some(false));
+        assert_eq!(
+            outgoing(r#"{"name": "a", "match": {"outgoing": true}, "actions": []}"#),
+            Some(true)
+        );
+        assert_eq!(
+            outgoing(r#"{"name": "a", "match": {"outgoing": null}, "actions": []}"#),
+            None
+        );
+    }
+
+    #[test]
+    fn example_rules_parse() {
+        let json = include_str!("../../rules.example.json");
+        let rules: Vec<Rule> = serde_json::from_str(json).unwrap();
+        assert_eq!(rules[0].conditions.outgoing, None);
+    }
+}
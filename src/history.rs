@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use grammers_client::{Client, InvocationError};
+use grammers_tl_types as tl;
+use log::{info, warn};
+
+use crate::commands::{Command, Context, Reply};
+use crate::storage::{BackfillCursor, Storage, storage};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Save the cursor and log progress this often, in messages.
+const CURSOR_EVERY: usize = 500;
+
+pub const USAGE: &str = "<chat id, Bot API form> [max messages]";
+
+pub const COMMAND: Command = Command {
+    name: "backfill",
+    usage: USAGE,
+    about: "store a chat's earlier history, resuming where the last run stopped",
+    run,
+};
+
+fn run<'a>(ctx: &'a Context, args: &'a str) -> Reply<'a> {
+    Box::pin(async move {
+        let (chat_id, limit) = parse(args)?;
+        backfill(&ctx.client, chat_id, limit, ctx.client_id).await
+    })
+}
+
+pub fn parse(args: &str) -> Result<(i64, Option<usize>), Error> {
+    let mut args = args.split_whitespace();
+    let chat_id = args
+        .next()
+        .ok_or_else(|| format!("usage: {USAGE}"))?
+        .parse()
+        .map_err(|_| format!("usage: {USAGE}"))?;
+    let limit = match args.next() {
+        Some(n) => Some(n.parse().map_err(|_| format!("invalid max messages: {n}"))?),
+        None => None,
+    };
+    Ok((chat_id, limit))
+}
+
+/// Walk a chat's history from newest to oldest and store the messages we don't
+/// have yet. Runs continue below the oldest message the previous run reached;
+/// at most `limit` messages are looked at per run.
+pub async fn backfill(
+    client: &Client,
+    chat_id: i64,
+    limit: Option<usize>,
+    client_id: u64,
+) -> Result<String, Error> {
+    let peer = crate::utils::peer::resolve(client, chat_id).await?;
+    let cursor = storage().backfill_cursor(chat_id).await?;
+    if cursor.as_ref().is_some_and(|c| c.done) {
+        return Ok(format!("history of {chat_id} is already backfilled"));
+    }
+    let mut oldest = cursor.map(|c| c.oldest_id).unwrap_or(0);
+    let (mut seen, mut stored) = (0, 0);
+    let mut done = false;
+
+    'fetch: loop {
+        // Offset 0 starts at the newest message.
+        let mut messages = client.iter_messages(peer).offset_id(oldest);
+        loop {
+            if limit.is_some_and(|l| seen >= l) {
+                break 'fetch;
+            }
+            let message = match messages.next().await {
+                Ok(Some(message)) => message,
+                Ok(None) => {
+                    done = true;
+                    break 'fetch;
+                }
+                Err(InvocationError::Rpc(e)) if e.name == "FLOOD_WAIT" => {
+                    let wait = e.value.unwrap_or(1);
+                    warn!("history of {chat_id}: flood wait of {wait}s");
+                    save_cursor(chat_id, oldest, false).await?;
+                    tokio::time::sleep(Duration::from_secs(wait as u64)).await;
+                    continue 'fetch;
+                }
+                Err(e) => {
+                    save_cursor(chat_id, oldest, false).await?;
+                    return Err(e.into());
+                }
+            };
+            seen += 1;
+            oldest = message.id();
+
+            let bare_id = message.peer_id().bare_id_unchecked();
+            if !matches!(message.raw, tl::enums::Message::Empty(_))
+                && !crate::handlers::message_exists(bare_id, message.id()).await
+            {
+                crate::handlers::save_fetched(client, &message, client_id).await;
+                stored += 1;
+            }
+
+            if seen % CURSOR_EVERY == 0 {
+                save_cursor(chat_id, oldest, false).await?;
+                info!(
+                    "\x1b[96m{:<8} {:>8} {:<25}\x1b[0m",
+                    "history", oldest, format!("{chat_id}: {stored}/{seen} new")
+                );
+            }
+        }
+    }
+    save_cursor(chat_id, oldest, done).await?;
+
+    let mut summary = format!("{chat_id}: looked at {seen} messages, stored {stored} new");
+    if done {
+        summary.push_str(", reached the start of the chat");
+    } else {
+        summary.push_str(&format!(", run again to continue below message {oldest}"));
+    }
+    info!("\x1b[96m{:<8} {:>8} {}\x1b[0m", "history", oldest, summary);
+    Ok(summary)
+}
+
+async fn save_cursor(chat_id: i64, oldest_id: i32, done: bool) -> Result<(), Error> {
+    // Nothing fetched yet: keep starting from the newest message.
+    if oldest_id == 0 && !done {
+        return Ok(());
+    }
+    let row = BackfillCursor {
+        chat_id,
+        oldest_id,
+        done,
+    };
+    storage().insert("backfill_cursors", &[row]).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    #[test]
+    fn parse_reads_chat_and_limit() {
+        assert_eq!(parse("-1001234567890").unwrap(), (-1_001_234_567_890, None));
+        assert_eq!(parse("  42   1000 ").unwrap(), (42, Some(1000)));
+    }
+
+    #[test]
+    fn parse_rejects_bad_arguments() {
+        assert!(parse("").is_err());
+        assert!(parse("@someone").is_err());
+        assert!(parse("42 lots").is_err());
+        assert!(parse("42 -5").is_err());
+    }
+}
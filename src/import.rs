@@ -0,0 +1,771 @@
+use std::fmt;
+use std::io::BufReader;
+
+use grammers_tl_types as tl;
+use grammers_tl_types::enums::MessageAction;
+use log::{info, warn};
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::db::{EditedMessage, IncomingMessage, OutgoingMessage};
+use crate::storage::{Storage, storage};
+use crate::utils::media_description::format_duration_secs;
+use crate::utils::service_action;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// What the parser thread hands to `import`, in file order. An export can be
+/// gigabytes, so it is read as a stream and only one message is held at a time.
+enum Item {
+    /// Our user id, from `personal_information` of a full export.
+    OwnId(u64),
+    /// A chat starts; its messages follow. The id is bare, and missing for
+    /// Saved Messages in older exports.
+    Chat { id: Option<i64>, name: String },
+    Message(Value),
+    ChatEnd,
+}
+
+/// Messages queued between the parser thread and the import loop.
+const QUEUED_ITEMS: usize = 1_000;
+
+fn send<E: de::Error>(tx: &mpsc::Sender<Item>, item: Item) -> Result<(), E> {
+    tx.blocking_send(item)
+        .map_err(|_| E::custom("import stopped"))
+}
+
+/// An object of the export: the whole `result.json`, `chats` or `left_chats`
+/// of a full export, or a chat. A single-chat export is a chat itself.
+/// Desktop writes a chat's `name` and `id` before its `messages`.
+#[derive(Clone, Copy)]
+struct Section<'a>(&'a mpsc::Sender<Item>);
+
+impl<'de> DeserializeSeed<'de> for Section<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Section<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a Telegram Desktop export object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut name: Option<String> = None;
+        let mut id: Option<i64> = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "personal_information" => {
+                    let info: Value = map.next_value()?;
+                    if let Some(user_id) = info.get("user_id").and_then(Value::as_u64) {
+                        send::<A::Error>(self.0, Item::OwnId(user_id))?;
+                    }
+                }
+                "chats" | "left_chats" => map.next_value_seed(self)?,
+                "list" => map.next_value_seed(Chats(self.0))?,
+                "name" => name = map.next_value()?,
+                "id" => id = map.next_value()?,
+                "messages" => {
+                    let name = name.clone().unwrap_or_default();
+                    send::<A::Error>(self.0, Item::Chat { id, name })?;
+                    map.next_value_seed(Messages(self.0))?;
+                    send::<A::Error>(self.0, Item::ChatEnd)?;
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The chats of `chats.list` or `left_chats.list`.
+struct Chats<'a>(&'a mpsc::Sender<Item>);
+
+impl<'de> DeserializeSeed<'de> for Chats<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Chats<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of chats")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while seq.next_element_seed(Section(self.0))?.is_some() {}
+        Ok(())
+    }
+}
+
+/// A chat's messages, sent on one by one.
+struct Messages<'a>(&'a mpsc::Sender<Item>);
+
+impl<'de> DeserializeSeed<'de> for Messages<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Messages<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of messages")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(message) = seq.next_element::<Value>()? {
+            send::<A::Error>(self.0, Item::Message(message))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct ExportMessage {
+    id: i32,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    date: String,
+    #[serde(default)]
+    date_unixtime: Option<String>,
+    #[serde(default)]
+    edited_unixtime: Option<String>,
+    #[serde(default)]
+    from: Option<String>,
+    #[serde(default)]
+    from_id: Option<String>,
+    #[serde(default)]
+    actor: Option<String>,
+    #[serde(default)]
+    actor_id: Option<String>,
+    #[serde(default)]
+    action: Option<String>,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    members: Vec<Option<String>>,
+    #[serde(default)]
+    duration_seconds: Option<i32>,
+    #[serde(default)]
+    text: Value,
+    #[serde(default)]
+    text_entities: Vec<TextEntity>,
+    #[serde(default)]
+    reply_to_message_id: Option<i32>,
+    #[serde(default)]
+    forwarded_from: Option<String>,
+    #[serde(default)]
+    photo: Option<String>,
+    #[serde(default)]
+    file: Option<String>,
+    #[serde(default)]
+    file_name: Option<String>,
+    #[serde(default)]
+    media_type: Option<String>,
+    #[serde(default)]
+    sticker_emoji: Option<String>,
+    #[serde(default)]
+    performer: Option<String>,
+    #[serde(default)]
+    contact_information: Option<Contact>,
+    #[serde(default)]
+    location_information: Option<Location>,
+    #[serde(default)]
+    live_location_period_seconds: Option<i32>,
+    #[serde(default)]
+    place_name: Option<String>,
+    #[serde(default)]
+    poll: Option<Poll>,
+    #[serde(default)]
+    game_title: Option<String>,
+    #[serde(default)]
+    invoice_information: Option<Invoice>,
+}
+
+#[derive(Deserialize)]
+struct TextEntity {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    href: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Contact {
+    #[serde(default)]
+    first_name: String,
+    #[serde(default)]
+    last_name: String,
+    #[serde(default)]
+    phone_number: String,
+}
+
+#[derive(Deserialize)]
+struct Location {
+    latitude: f64,
+    longitude: f64,
+}
+
+#[derive(Deserialize)]
+struct Poll {
+    #[serde(default)]
+    question: String,
+    #[serde(default)]
+    answers: Vec<PollAnswer>,
+}
+
+#[derive(Deserialize)]
+struct PollAnswer {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct Invoice {
+    #[serde(default)]
+    title: String,
+}
+
+#[derive(Default)]
+struct Counts {
+    chats: usize,
+    imported: usize,
+    skipped: usize,
+    /// Messages in a shape the export structs do not cover.
+    unreadable: usize,
+}
+
+/// Import a Telegram Desktop JSON export (`result.json`) into chats_log and
+/// telegram_messages_new. Our own messages go to the latter, edited ones also
+/// get an edit log row with an unknown original. Messages already stored are
+/// skipped, so importing the same export again adds nothing.
+pub async fn import(path: &str) -> Result<String, Error> {
+    let file = std::fs::File::open(path).map_err(|e| format!("{path}: {e}"))?;
+    let (tx, mut rx) = mpsc::channel(QUEUED_ITEMS);
+    let parser = tokio::task::spawn_blocking(move || {
+        let mut de = serde_json::Deserializer::from_reader(BufReader::new(file));
+        Section(&tx).deserialize(&mut de)?;
+        de.end()
+    });
+
+    let mut own_id = None;
+    let mut chat: Option<(i64, String)> = None;
+    let mut counts = Counts::default();
+    let mut before = Counts::default();
+    while let Some(item) = rx.recv().await {
+        match item {
+            Item::OwnId(id) => own_id = Some(id),
+            Item::Chat { id, name } => {
+                chat = id.map(|id| (id, name));
+                if chat.is_some() {
+                    counts.chats += 1;
+                }
+                before.imported = counts.imported;
+                before.unreadable = counts.unreadable;
+            }
+            Item::Message(raw) => {
+                let Some((chat_id, title)) = &chat else {
+                    continue;
+                };
+                let message = match ExportMessage::deserialize(&raw) {
+                    Ok(m) => m,
+                    Err(e) => {
+                        let id = raw.get("id").cloned().unwrap_or_default();
+                        warn!("import: chat {chat_id} message {id} skipped, unreadable: {e}");
+                        counts.unreadable += 1;
+                        continue;
+                    }
+                };
+                if crate::handlers::message_exists(*chat_id, message.id).await {
+                    counts.skipped += 1;
+                    continue;
+                }
+                let own_id = match own_id {
+                    Some(id) => id,
+                    None => {
+                        let id = stored_own_id().await?;
+                        own_id = Some(id);
+                        id
+                    }
+                };
+                save(*chat_id, title, &message, raw.to_string(), own_id).await;
+                counts.imported += 1;
+            }
+            Item::ChatEnd => {
+                let Some((chat_id, title)) = chat.take() else {
+                    continue;
+                };
+                let title_short: String = title.chars().take(25).collect();
+                info!(
+                    "\x1b[96m{:<8} {:>8} {:<25}\x1b[0m {} new, {} unreadable",
+                    "import",
+                    chat_id,
+                    title_short,
+                    counts.imported - before.imported,
+                    counts.unreadable - before.unreadable
+                );
+            }
+        }
+    }
+
+    let summary = format!(
+        "imported {} messages from {} chats, skipped {} already stored and {} unreadable",
+        counts.imported, counts.chats, counts.skipped, counts.unreadable
+    );
+    match parser.await? {
+        Ok(()) => Ok(format!("{path}: {summary}")),
+        Err(e) => Err(format!("{path}: {e}; {summary} before the error").into()),
+    }
+}
+
+/// Our user id when the export does not say: a single-chat export.
+async fn stored_own_id() -> Result<u64, Error> {
+    match storage().peer(None).await? {
+        Some(row) => Ok(row.peer_id as u64),
+        None => Err("own user id unknown: log in once or use a full export".into()),
+    }
+}
+
+/// The row of an exported message.
+enum Row {
+    Incoming(IncomingMessage),
+    Outgoing(OutgoingMessage),
+}
+
+async fn save(chat_id: i64, title: &str, message: &ExportMessage, raw: String, own_id: u64) {
+    let (row, edit) = rows(chat_id, title, message, raw, own_id);
+    match row {
+        Row::Incoming(row) => crate::db::INCOMING_BUF.push(row).await,
+        Row::Outgoing(row) => crate::db::OUTGOING_BUF.push(row).await,
+    }
+    if let Some(edit) = edit {
+        crate::db::EDITED_BUF.push(edit).await;
+    }
+}
+
+/// Map an exported message to its row, plus an edit log row if it was edited.
+fn rows(
+    chat_id: i64,
+    title: &str,
+    message: &ExportMessage,
+    raw: String,
+    own_id: u64,
+) -> (Row, Option<EditedMessage>) {
+    let date_time = unixtime(message.date_unixtime.as_deref())
+        .or_else(|| parse_date(&message.date))
+        .unwrap_or(0);
+    let sender_name = message
+        .from
+        .clone()
+        .or_else(|| message.actor.clone())
+        .unwrap_or_default();
+    let sender_id = bare_id(message.from_id.as_deref().or(message.actor_id.as_deref()));
+    let outgoing = sender_id == Some(own_id as i64);
+
+    let media = describe_media(message).unwrap_or_default();
+    let text = formatted_text(message);
+    let content = if message.kind == "service" {
+        format_action(message, sender_id, &sender_name)
+    } else if !text.is_empty() {
+        text
+    } else {
+        media.clone()
+    };
+    let reply_to = message.reply_to_message_id.unwrap_or(0) as u64;
+
+    let row = if outgoing {
+        Row::Outgoing(OutgoingMessage {
+            date_time,
+            message: content.clone(),
+            title: title.to_string(),
+            id: chat_id,
+            admins2: Vec::new(),
+            usernames: Vec::new(),
+            message_id: message.id as u64,
+            reply_to,
+            raw,
+            client_id: own_id,
+            media: media.clone(),
+            topic_id: 0,
+            topic_name: String::new(),
+            fwd_from_id: 0,
+            fwd_from_name: message.forwarded_from.clone().unwrap_or_default(),
+            fwd_message_id: 0,
+            fwd_date: 0,
+            fwd_saved_from_id: 0,
+            fwd_saved_from_message_id: 0,
+            grouped_id: 0,
+            media_id: 0,
+        })
+    } else {
+        Row::Incoming(IncomingMessage {
+            date_time,
+            message: content.clone(),
+            chat_title: title.to_string(),
+            chat_id,
+            username: Vec::new(),
+            first_name: sender_name,
+            second_name: String::new(),
+            // Channel posts have no user sender, as with live messages.
+            user_id: user_id(message.from_id.as_deref().or(message.actor_id.as_deref())),
+            community_tag: String::new(),
+            message_id: message.id as i64,
+            chat_usernames: Vec::new(),
+            reply_to,
+            client_id: own_id,
+            raw,
+            media: media.clone(),
+            topic_id: 0,
+            topic_name: String::new(),
+            fwd_from_id: 0,
+            fwd_from_name: message.forwarded_from.clone().unwrap_or_default(),
+            fwd_message_id: 0,
+            fwd_date: 0,
+            fwd_saved_from_id: 0,
+            fwd_saved_from_message_id: 0,
+            grouped_id: 0,
+            media_id: 0,
+        })
+    };
+
+    // The export only has the final version; record that it was edited.
+    let edit = unixtime(message.edited_unixtime.as_deref()).map(|edited| EditedMessage {
+        date_time: edited,
+        chat_id,
+        message_id: message.id as i64,
+        original_message: String::new(),
+        message: content,
+        diff: String::new(),
+        user_id: sender_id.unwrap_or(0),
+        client_id: own_id,
+        outgoing,
+        original_unknown: true,
+        original_media: String::new(),
+        media,
+        original_media_id: 0,
+        media_id: 0,
+    });
+    (row, edit)
+}
+
+fn unixtime(s: Option<&str>) -> Option<u32> {
+    s?.parse().ok()
+}
+
+/// Older exports only have the local `date`, taken as UTC.
+fn parse_date(s: &str) -> Option<u32> {
+    chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S")
+        .ok()
+        .map(|d| d.and_utc().timestamp() as u32)
+}
+
+/// Bare id from an export id like "user123" or "channel456".
+fn bare_id(id: Option<&str>) -> Option<i64> {
+    let id = id?;
+    id.trim_start_matches(|c: char| c.is_ascii_alphabetic()).parse().ok()
+}
+
+fn user_id(id: Option<&str>) -> u64 {
+    match id {
+        Some(id) if id.starts_with("user") => bare_id(Some(id)).unwrap_or(0) as u64,
+        _ => 0,
+    }
+}
+
+/// Message text with the same markers `format_entities` uses for live messages.
+fn formatted_text(message: &ExportMessage) -> String {
+    if !message.text_entities.is_empty() {
+        return message.text_entities.iter().map(format_entity).collect();
+    }
+    // Older exports only have `text`: a string, or a mix of strings and entities.
+    match &message.text {
+        Value::String(s) => s.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .map(|p| match p {
+                Value::String(s) => s.clone(),
+                _ => serde_json::from_value::<TextEntity>(p.clone())
+                    .map(|e| format_entity(&e))
+                    .unwrap_or_default(),
+            })
+            .collect(),
+        _ => String::new(),
+    }
+}
+
+fn format_entity(e: &TextEntity) -> String {
+    let t = &e.text;
+    match e.kind.as_str() {
+        "code" => format!("`{t}`"),
+        "pre" => format!("```\n{t}\n```"),
+        "strikethrough" => format!("~~{t}~~"),
+        "underline" => format!("__{t}__"),
+        "spoiler" => format!("||{t}||"),
+        "text_link" => match &e.href {
+            Some(href) => format!("[{t}]({href})"),
+            None => t.clone(),
+        },
+        "blockquote" => t.lines().map(|l| format!("> {l}")).collect::<Vec<_>>().join("\n"),
+        _ => t.clone(),
+    }
+}
+
+/// Media description in `media_description` wording, from the export fields.
+fn describe_media(m: &ExportMessage) -> Option<String> {
+    let duration = || format_duration_secs(m.duration_seconds.unwrap_or(0));
+    if m.photo.is_some() {
+        return Some("[photo]".into());
+    }
+    if m.file.is_some() {
+        return Some(match m.media_type.as_deref() {
+            Some("sticker") => format!("[sticker {}]", m.sticker_emoji.as_deref().unwrap_or("")),
+            Some("voice_message") => format!("[voice, {}]", duration()),
+            Some("video_message") => format!("[video message, {}]", duration()),
+            Some("animation") => "[GIF]".into(),
+            Some("video_file") => format!("[video, {}]", duration()),
+            Some("audio_file") => {
+                let mut parts = vec!["audio".to_string()];
+                match (m.performer.as_deref(), m.title.as_deref()) {
+                    (Some(p), Some(t)) => parts.push(format!("{p} — {t}")),
+                    (Some(s), None) | (None, Some(s)) => parts.push(s.to_string()),
+                    (None, None) => {}
+                }
+                parts.push(duration());
+                format!("[{}]", parts.join(", "))
+            }
+            _ => match &m.file_name {
+                Some(name) => format!("[file, {name}]"),
+                None => "[document]".into(),
+            },
+        });
+    }
+    if let Some(c) = &m.contact_information {
+        let name = [c.first_name.as_str(), c.last_name.as_str()]
+            .iter()
+            .filter(|s| !s.is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ");
+        return Some(if name.is_empty() {
+            format!("[contact, {}]", c.phone_number)
+        } else {
+            format!("[contact, {}, {}]", name, c.phone_number)
+        });
+    }
+    if let Some(place) = &m.place_name {
+        return Some(format!("[venue, {place}]"));
+    }
+    if let Some(l) = &m.location_information {
+        let kind = if m.live_location_period_seconds.is_some() { "live location" } else { "location" };
+        return Some(format!("[{kind}, {:.5}, {:.5}]", l.latitude, l.longitude));
+    }
+    if let Some(p) = &m.poll {
+        if p.answers.is_empty() {
+            return Some(format!("[poll: {}]", p.question));
+        }
+        let opts = p.answers.iter()
+            .map(|a| format!("* {}", a.text))
+            .collect::<Vec<_>>()
+            .join("\n");
+        return Some(format!("[poll: {}]\n{}", p.question, opts));
+    }
+    if let Some(g) = &m.game_title {
+        return Some(format!("[game, {g}]"));
+    }
+    if let Some(inv) = &m.invoice_information {
+        return Some(format!("[invoice, {}]", inv.title));
+    }
+    None
+}
+
+/// Service message text through `service_action::format` where the export
+/// carries what the action needs; exports name members instead of listing ids.
+fn format_action(m: &ExportMessage, sender_id: Option<i64>, sender_name: &str) -> String {
+    let title = m.title.clone().unwrap_or_default();
+    let members: Vec<&str> = m.members.iter().flatten().map(String::as_str).collect();
+    let only_sender = members == [sender_name];
+    let action = match m.action.as_deref().unwrap_or_default() {
+        "create_group" => {
+            return format!("[chat created: \"{title}\", members: {}]", members.join(", "));
+        }
+        "edit_group_title" => MessageAction::ChatEditTitle(tl::types::MessageActionChatEditTitle { title }),
+        "edit_group_photo" => return "[chat photo updated]".into(),
+        "delete_group_photo" => MessageAction::ChatDeletePhoto,
+        "invite_members" if only_sender => MessageAction::ChatAddUser(tl::types::MessageActionChatAddUser {
+            users: sender_id.into_iter().collect(),
+        }),
+        "invite_members" => return format!("[users added: {}]", members.join(", ")),
+        "remove_members" if only_sender => MessageAction::ChatDeleteUser(tl::types::MessageActionChatDeleteUser {
+            user_id: sender_id.unwrap_or(0),
+        }),
+        "remove_members" => return format!("[user removed: {}]", members.join(", ")),
+        "join_group_by_link" => MessageAction::ChatJoinedByLink(tl::types::MessageActionChatJoinedByLink {
+            inviter_id: 0,
+        }),
+        "join_group_by_request" => MessageAction::ChatJoinedByRequest,
+        "create_channel" => MessageAction::ChannelCreate(tl::types::MessageActionChannelCreate { title }),
+        "pin_message" => MessageAction::PinMessage,
+        "clear_history" => MessageAction::HistoryClear,
+        "take_screenshot" => MessageAction::ScreenshotTaken,
+        "joined_telegram" => MessageAction::ContactSignUp,
+        "phone_call" => MessageAction::PhoneCall(tl::types::MessageActionPhoneCall {
+            video: false,
+            call_id: 0,
+            reason: None,
+            duration: m.duration_seconds,
+        }),
+        other => return format!("[{}]", other.replace('_', " ")),
+    };
+    service_action::format(&action, sender_id, Some(sender_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::{Value, json};
+
+    use super::{ExportMessage, Row, import, rows};
+    use crate::db::{EDITED_BUF, INCOMING_BUF, OUTGOING_BUF};
+
+    fn message(value: Value) -> ExportMessage {
+        ExportMessage::deserialize(&value).unwrap()
+    }
+
+    #[test]
+    fn edited_own_message_maps_to_outgoing_and_an_edit() {
+        // Older exports mix strings and entities in `text`.
+        let m = message(json!({
+            "id": 5,
+            "type": "message",
+            "date": "2024-03-01T10:00:00",
+            "edited_unixtime": "1709290000",
+            "from": "Me",
+            "from_id": "user42",
+            "text": ["see ", {"type": "text_link", "text": "docs", "href": "https://x.y"}, " and ", {"type": "code", "text": "ls"}]
+        }));
+        let (row, edit) = rows(-7, "chat", &m, "{}".to_string(), 42);
+        let Row::Outgoing(row) = row else {
+            panic!("our own message must be outgoing");
+        };
+        assert_eq!(row.message, "see [docs](https://x.y) and `ls`");
+        assert_eq!(row.id, -7);
+        assert_eq!(row.message_id, 5);
+        assert_eq!(row.date_time, 1_709_287_200);
+        let edit = edit.unwrap();
+        assert_eq!(edit.date_time, 1_709_290_000);
+        assert_eq!(edit.message, row.message);
+        assert!(edit.original_unknown && edit.outgoing);
+        assert_eq!(edit.user_id, 42);
+    }
+
+    #[test]
+    fn others_message_maps_to_incoming() {
+        let m = message(json!({
+            "id": 6,
+            "type": "message",
+            "date_unixtime": "1700000000",
+            "from": "Ann",
+            "from_id": "user7",
+            "reply_to_message_id": 5,
+            "photo": "photos/1.jpg",
+            "text": "",
+            "text_entities": [
+                {"type": "plain", "text": "quote:\n"},
+                {"type": "blockquote", "text": "a\nb"}
+            ]
+        }));
+        let (row, edit) = rows(-7, "chat", &m, "{}".to_string(), 42);
+        let Row::Incoming(row) = row else {
+            panic!("a message from someone else must be incoming");
+        };
+        assert_eq!(row.message, "quote:\n> a\n> b");
+        assert_eq!(row.media, "[photo]");
+        assert_eq!((row.user_id, row.first_name.as_str()), (7, "Ann"));
+        assert_eq!(row.reply_to, 5);
+        assert!(edit.is_none());
+
+        // Channel posts have no user sender; captionless media keeps its description.
+        let post = message(json!({"id": 7, "type": "message", "from_id": "channel9", "photo": "p.jpg", "text": ""}));
+        let (Row::Incoming(row), _) = rows(-7, "chat", &post, "{}".to_string(), 42) else {
+            panic!("a channel post must be incoming");
+        };
+        assert_eq!(row.user_id, 0);
+        assert_eq!(row.message, "[photo]");
+    }
+
+    #[tokio::test]
+    async fn import_streams_a_full_export_once() {
+        let export = json!({
+            "about": "",
+            "personal_information": {"user_id": 42, "first_name": "Me"},
+            "contacts": {"about": "", "list": [{"first_name": "Ann"}]},
+            "chats": {"about": "", "list": [
+                {"name": "Group", "type": "private_group", "id": 9601, "messages": [
+                    {"id": 1, "type": "message", "date_unixtime": "1700000000", "from": "Ann", "from_id": "user7", "text": "hi"},
+                    {"id": 2, "type": "message", "date_unixtime": "1700000001", "from": "Me", "from_id": "user42", "text": "hello",
+                     "edited_unixtime": "1700000100"},
+                    {"id": "x", "type": "message"}
+                ]},
+                {"name": "Saved", "type": "saved_messages", "messages": [
+                    {"id": 1, "type": "message", "text": "skipped, no chat id"}
+                ]}
+            ]},
+            "left_chats": {"about": "", "list": [
+                {"name": "Old", "type": "private_supergroup", "id": 9602, "messages": [
+                    {"id": 3, "type": "message", "date_unixtime": "1700000002", "from": "Bob", "from_id": "user8", "text": "bye"}
+                ]}
+            ]}
+        });
+        let path = std::env::temp_dir().join(format!("import-test-{}.json", std::process::id()));
+        std::fs::write(&path, export.to_string()).unwrap();
+        let path = path.to_str().unwrap();
+
+        let summary = import(path).await.unwrap();
+        assert!(
+            summary.ends_with("imported 3 messages from 2 chats, skipped 0 already stored and 1 unreadable"),
+            "{summary}"
+        );
+        let hi = INCOMING_BUF.find_last(|m| (m.chat_id == 9601).then(|| m.message.clone())).await;
+        assert_eq!(hi.as_deref(), Some("hi"));
+        let hello = OUTGOING_BUF.find_last(|m| (m.id == 9601).then(|| m.message.clone())).await;
+        assert_eq!(hello.as_deref(), Some("hello"));
+        let edited = EDITED_BUF.find_last(|e| (e.chat_id == 9601).then_some(e.message_id)).await;
+        assert_eq!(edited, Some(2));
+        let bye = INCOMING_BUF.find_last(|m| (m.chat_id == 9602).then(|| m.message.clone())).await;
+        assert_eq!(bye.as_deref(), Some("bye"));
+
+        let again = import(path).await.unwrap();
+        let _ = std::fs::remove_file(path);
+        assert!(again.contains("imported 0 messages from 2 chats, skipped 3"), "{again}");
+    }
+
+    #[tokio::test]
+    async fn import_reports_a_broken_file() {
+        let path = std::env::temp_dir().join(format!("import-broken-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"personal_information": {"user_id": 42}, "chats": {"list": [{"id": 9603, "messages": [{"id": 1, "type": "message", "text": "kept"}, "#).unwrap();
+        let result = import(path.to_str().unwrap()).await;
+        let _ = std::fs::remove_file(&path);
+        let e = result.unwrap_err().to_string();
+        assert!(e.contains("imported 1 messages from 1 chats"), "{e}");
+    }
+}
@@ -1,8 +1,19 @@
-mod clickhouse_session;
+mod catch_up;
+mod commands;
 mod db;
 mod handlers;
+mod history;
+mod import;
+mod media_archive;
+mod message_cache;
+mod migrations;
+mod notifier;
 mod schedulers;
+mod search;
 mod session;
+mod spool;
+mod storage;
+mod storage_session;
 mod utils;
 
 use grammers_client::update::Update;
@@ -39,12 +50,65 @@ async fn main() -> Result<()> {
         log::error!("{}\n{}", info, backtrace);
     }));
 
+    if storage::storage().is_clickhouse() {
+        migrations::run().await?;
+    }
+    if env::args().nth(1).as_deref() == Some("migrate") {
+        return Ok(());
+    }
+    if env::args().nth(1).as_deref() == Some("search") {
+        // Keep phrases the shell unquoted together.
+        let query = env::args()
+            .skip(2)
+            .map(|a| if a.contains(' ') { format!("\"{a}\"") } else { a })
+            .collect::<Vec<_>>()
+            .join(" ");
+        match search::search(&query).await {
+            Ok(results) => println!("{results}"),
+            Err(e) => return Err(e.to_string().into()),
+        }
+        return Ok(());
+    }
+    if env::args().nth(1).as_deref() == Some("backfill") {
+        let args = env::args().skip(2).collect::<Vec<_>>().join(" ");
+        let (chat_id, limit) = history::parse(&args).map_err(|e| e.to_string())?;
+        let client = session::connect_without_updates().await?;
+        schedulers::start_flusher();
+        let client_id = client.get_me().await?.id().bare_id().unwrap() as u64;
+        let result = history::backfill(&client, chat_id, limit, client_id).await;
+        schedulers::flush_all().await;
+        match result {
+            Ok(summary) => println!("{summary}"),
+            Err(e) => return Err(e.to_string().into()),
+        }
+        return Ok(());
+    }
+    if env::args().nth(1).as_deref() == Some("import") {
+        let path = env::args().nth(2).ok_or("usage: import <path to result.json>")?;
+        schedulers::start_flusher();
+        let result = import::import(&path).await;
+        schedulers::flush_all().await;
+        match result {
+            Ok(summary) => println!("{summary}"),
+            Err(e) => return Err(e.to_string().into()),
+        }
+        return Ok(());
+    }
+    handlers::load_rules();
+    commands::init();
+    commands::register(search::COMMAND);
+    commands::register(history::COMMAND);
+    message_cache::warm().await;
+    schedulers::replay_spool().await;
+
     let (client, mut updates): (grammers_client::Client, _) = session::connect().await?;
 
     log::info!("Listening for messages...");
 
     let client_id = client.get_me().await?.id().bare_id().unwrap() as u64;
     schedulers::start(client.clone(), client_id);
+    notifier::start(client.clone());
+    catch_up::report();
 
     let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
 
@@ -54,21 +118,33 @@ async fn main() -> Result<()> {
                 let update = update?;
                 match update {
                     Update::NewMessage(message) => {
+                        let missed = catch_up::observe(message.date().timestamp());
                         handlers::backfill_reply(&client, &message, client_id).await;
+                        media_archive::archive(&client, &message, client_id);
                         if message.outgoing() {
-                            if let Err(e) = handlers::save_outgoing(&message, client_id).await {
+                            if let Err(e) = handlers::save_outgoing(&client, &message, client_id).await {
                                 error!("Failed to save outgoing message: {:?}", e);
                             }
                         } else {
-                            if let Err(e) = handlers::save_incoming(&message, client_id).await {
+                            if let Err(e) = handlers::save_incoming(&client, &message, client_id).await {
                                 error!("Failed to save incoming message: {:?}", e);
                             }
                         }
-                        if let Err(e) = handlers::handle_auto_cat(&message).await {
-                            error!("Failed to handle auto cat: {:?}", e);
+                        // Commands and rules were meant for the moment a message
+                        // arrived, not for one recovered by a catch-up.
+                        if !missed {
+                            if message.outgoing() {
+                                commands::dispatch(&client, &message, client_id);
+                            }
+                            if let Err(e) = handlers::handle_rules(&client, &message).await {
+                                error!("Failed to handle rules: {:?}", e);
+                            }
                         }
                     }
                     Update::MessageEdited(message) => {
+                        if let Err(e) = handlers::save_message_reactions(&message, client_id).await {
+                            error!("Failed to save reactions: {:?}", e);
+                        }
                         if let Err(e) = handlers::save_edited(&message, client_id).await {
                             error!("Failed to save edited message: {:?}", e);
                         }
@@ -78,13 +154,19 @@ async fn main() -> Result<()> {
                             error!("Failed to save deleted message: {:?}", e);
                         }
                     }
-                    Update::Raw(raw) => {
-                        if let tl::enums::Update::PendingJoinRequests(u) = &raw.raw {
+                    Update::Raw(raw) => match &raw.raw {
+                        tl::enums::Update::PendingJoinRequests(u) => {
                             if let Err(e) = handlers::handle_pending_join_requests(u, client_id).await {
                                 error!("Failed to handle pending join requests: {:?}", e);
                             }
                         }
-                    }
+                        tl::enums::Update::MessageReactions(u) => {
+                            if let Err(e) = handlers::save_reactions(u, client_id).await {
+                                error!("Failed to save reactions: {:?}", e);
+                            }
+                        }
+                        _ => {}
+                    },
                     _ => {}
                 }
             }
@@ -0,0 +1,200 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+use grammers_client::Client;
+use grammers_client::update::Message;
+use grammers_tl_types as tl;
+use log::{error, info, warn};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
+
+use crate::db::MediaFile;
+use crate::utils::log_ignore::is_log_ignored;
+
+/// Opt-in media archive, enabled by setting `MEDIA_ARCHIVE_DIR`.
+///
+/// Photos and documents are downloaded into `<dir>/<h[0..2]>/<sha256>`, so the
+/// same file sent twice is stored once, and recorded in `media_log`.
+/// `MEDIA_ARCHIVE_MAX_BYTES` (default 20 MiB) skips larger files and
+/// `MEDIA_ARCHIVE_CHATS` (comma-separated chat ids, Bot API form) limits archiving
+/// to those chats.
+static ARCHIVE_DIR: LazyLock<Option<PathBuf>> = LazyLock::new(|| {
+    let dir = PathBuf::from(std::env::var("MEDIA_ARCHIVE_DIR").ok()?);
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        error!(
+            "cannot create media archive dir {}: {e}, archiving disabled",
+            dir.display()
+        );
+        return None;
+    }
+    info!("archiving media to {}", dir.display());
+    Some(dir)
+});
+
+static MAX_BYTES: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("MEDIA_ARCHIVE_MAX_BYTES")
+        .map(|v| v.parse().expect("MEDIA_ARCHIVE_MAX_BYTES invalid"))
+        .unwrap_or(20 << 20)
+});
+
+static CHATS: LazyLock<Option<HashSet<i64>>> = LazyLock::new(|| {
+    let chats = std::env::var("MEDIA_ARCHIVE_CHATS").ok()?;
+    Some(
+        chats
+            .split(',')
+            .filter_map(|s| s.trim().parse().ok())
+            .map(crate::utils::peer::bare_id)
+            .collect(),
+    )
+});
+
+/// Downloads run in the background; this bounds how many run at once.
+static DOWNLOADS: Semaphore = Semaphore::const_new(4);
+
+/// Archive the message's photo or document in the background, if enabled and
+/// within the limits.
+pub fn archive(client: &Client, message: &Message, client_id: u64) {
+    let Some(dir) = ARCHIVE_DIR.as_ref() else {
+        return;
+    };
+    let chat_id = message.peer_id().bare_id_unchecked();
+    if CHATS
+        .as_ref()
+        .is_some_and(|chats| !chats.contains(&chat_id))
+    {
+        return;
+    }
+    let Some((size, mime)) =
+        crate::utils::media_description::extract_media(message).and_then(size_and_mime)
+    else {
+        return;
+    };
+    if size > *MAX_BYTES {
+        return;
+    }
+    let Some(media) = message.media() else {
+        return;
+    };
+
+    let client = client.clone();
+    let message_id = message.id() as i64;
+    tokio::spawn(async move {
+        let _permit = DOWNLOADS.acquire().await;
+        match download(&client, &media, dir).await {
+            Ok(Some((sha256, path, size))) => {
+                if !is_log_ignored(chat_id) {
+                    info!(
+                        "\x1b[90m{:<8} {:>8} {:<25} {}\x1b[0m",
+                        "media", message_id, mime, size,
+                    );
+                }
+                crate::db::MEDIA_BUF
+                    .push(MediaFile {
+                        date_time: chrono::Utc::now().timestamp() as u32,
+                        chat_id,
+                        message_id,
+                        sha256,
+                        mime,
+                        size,
+                        path: path.to_string_lossy().into_owned(),
+                        client_id,
+                    })
+                    .await;
+            }
+            Ok(None) => warn!("media {message_id} in {chat_id} exceeds the size limit, skipped"),
+            Err(e) => error!("failed to archive media {message_id} in {chat_id}: {e}"),
+        }
+    });
+}
+
+/// Expected size and mime type of downloadable media.
+fn size_and_mime(media: &tl::enums::MessageMedia) -> Option<(u64, String)> {
+    match media {
+        tl::enums::MessageMedia::Photo(p) => {
+            let tl::enums::Photo::Photo(photo) = p.photo.as_ref()? else {
+                return None;
+            };
+            let size = photo
+                .sizes
+                .iter()
+                .filter_map(|s| match s {
+                    tl::enums::PhotoSize::Size(s) => Some(s.size),
+                    tl::enums::PhotoSize::Progressive(s) => s.sizes.iter().max().copied(),
+                    _ => None,
+                })
+                .max()?;
+            Some((size as u64, "image/jpeg".to_string()))
+        }
+        tl::enums::MessageMedia::Document(d) => {
+            let tl::enums::Document::Document(doc) = d.document.as_ref()? else {
+                return None;
+            };
+            Some((doc.size as u64, doc.mime_type.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// Stream the media into a temporary file while hashing it, then move it to its
+/// content address. Returns `None` if it turns out larger than the limit.
+async fn download(
+    client: &Client,
+    media: &grammers_client::media::Media,
+    dir: &std::path::Path,
+) -> Result<Option<(String, PathBuf, u64)>, Box<dyn std::error::Error + Send + Sync>> {
+    let tmp = dir.join(format!(
+        "{}.part",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos()
+    ));
+    let fetched = fetch(client, media, &tmp).await;
+    let (sha256, size) = match fetched {
+        Ok(Some(found)) => found,
+        other => {
+            let _ = tokio::fs::remove_file(&tmp).await;
+            return other.map(|_| None);
+        }
+    };
+
+    let subdir = dir.join(&sha256[..2]);
+    tokio::fs::create_dir_all(&subdir).await?;
+    let path = subdir.join(&sha256);
+    if tokio::fs::try_exists(&path).await? {
+        tokio::fs::remove_file(&tmp).await?;
+    } else {
+        tokio::fs::rename(&tmp, &path).await?;
+    }
+    Ok(Some((sha256, path, size)))
+}
+
+/// Write the media to `tmp`, returning its sha256 and size.
+async fn fetch(
+    client: &Client,
+    media: &grammers_client::media::Media,
+    tmp: &std::path::Path,
+) -> Result<Option<(String, u64)>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut file = tokio::fs::File::create(tmp).await?;
+    let mut hasher = Sha256::new();
+    let mut size = 0u64;
+
+    let mut chunks = client.iter_download(media);
+    while let Some(chunk) = chunks.next().await? {
+        size += chunk.len() as u64;
+        if size > *MAX_BYTES {
+            return Ok(None);
+        }
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+
+    let sha256 = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    Ok(Some((sha256, size)))
+}
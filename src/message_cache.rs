@@ -0,0 +1,258 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{LazyLock, Mutex};
+
+use grammers_session::types::{PeerId, PeerKind};
+use log::{info, warn};
+
+use crate::storage::{CHANNEL_ID_OFFSET, Storage, storage};
+
+/// Bounded in-process index of recently seen messages, so edits, deletions and
+/// reply previews resolve without a storage round trip.
+///
+/// Filled from every row pushed into a `WriteBuffer` and warmed from storage at
+/// startup. Holds at most `MESSAGE_CACHE_SIZE` messages (default 100 000) and
+/// evicts the oldest first.
+static CACHE: LazyLock<Mutex<Cache>> = LazyLock::new(|| {
+    let capacity = std::env::var("MESSAGE_CACHE_SIZE")
+        .map(|v| v.parse().expect("MESSAGE_CACHE_SIZE invalid"))
+        .unwrap_or(100_000);
+    Mutex::new(Cache {
+        capacity,
+        messages: HashMap::new(),
+        order: VecDeque::new(),
+        titles: HashMap::new(),
+        private: HashMap::new(),
+        private_order: VecDeque::new(),
+    })
+});
+
+#[derive(Clone)]
+pub struct CachedMessage {
+    /// Latest known text, edits applied.
+    pub text: String,
+    pub chat_title: String,
+    pub first_name: String,
+    pub second_name: String,
+    pub user_id: u64,
+    pub date_time: u32,
+    pub media: String,
+    /// Photo or document id, 0 if unknown.
+    pub media_id: i64,
+    /// Sent by us; there is no sender name to show.
+    pub outgoing: bool,
+}
+
+impl CachedMessage {
+    pub fn sender(&self) -> Option<String> {
+        if self.outgoing {
+            None
+        } else if self.second_name.is_empty() {
+            Some(self.first_name.clone())
+        } else {
+            Some(format!("{} {}", self.first_name, self.second_name))
+        }
+    }
+}
+
+struct Cache {
+    capacity: usize,
+    messages: HashMap<(i64, i64), CachedMessage>,
+    /// First-insertion order, oldest first.
+    order: VecDeque<(i64, i64)>,
+    /// Latest chat title per chat, from incoming messages.
+    titles: HashMap<i64, String>,
+    /// message_id → chat_id for private chats and basic groups, where message ids
+    /// are unique per account and deletions arrive without a chat.
+    private: HashMap<i64, i64>,
+    private_order: VecDeque<i64>,
+}
+
+impl Cache {
+    fn insert(&mut self, key: (i64, i64), message: CachedMessage) {
+        if !message.outgoing && !message.chat_title.is_empty() {
+            self.titles.insert(key.0, message.chat_title.clone());
+        }
+        if self.messages.insert(key, message).is_none() {
+            self.order.push_back(key);
+        }
+        while self.messages.len() > self.capacity {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            self.messages.remove(&oldest);
+        }
+    }
+}
+
+pub fn get(chat_id: i64, message_id: i64) -> Option<CachedMessage> {
+    CACHE.lock().unwrap().messages.get(&(chat_id, message_id)).cloned()
+}
+
+pub fn len() -> usize {
+    CACHE.lock().unwrap().messages.len()
+}
+
+pub fn contains(chat_id: i64, message_id: i64) -> bool {
+    CACHE.lock().unwrap().messages.contains_key(&(chat_id, message_id))
+}
+
+pub fn chat_title(chat_id: i64) -> Option<String> {
+    CACHE.lock().unwrap().titles.get(&chat_id).cloned()
+}
+
+/// Record a message, replacing what is cached for it.
+pub fn insert(chat_id: i64, message_id: i64, message: CachedMessage) {
+    CACHE.lock().unwrap().insert((chat_id, message_id), message);
+}
+
+/// Record a message found in storage unless a newer copy is already cached.
+pub fn insert_if_absent(chat_id: i64, message_id: i64, message: CachedMessage) {
+    let mut cache = CACHE.lock().unwrap();
+    if !cache.messages.contains_key(&(chat_id, message_id)) {
+        cache.insert((chat_id, message_id), message);
+    }
+}
+
+/// Apply an edit to a cached message; unknown messages are left alone.
+pub fn update_text(chat_id: i64, message_id: i64, text: &str) {
+    if let Some(m) = CACHE.lock().unwrap().messages.get_mut(&(chat_id, message_id)) {
+        m.text = text.to_string();
+    }
+}
+
+/// Apply an edit that replaced or removed the media of a cached message.
+pub fn update_media(chat_id: i64, message_id: i64, media: &str, media_id: i64) {
+    if let Some(m) = CACHE.lock().unwrap().messages.get_mut(&(chat_id, message_id)) {
+        m.media = media.to_string();
+        m.media_id = media_id;
+    }
+}
+
+/// Index a message for `private_chat` unless it belongs to a channel.
+pub fn index_if_private(peer: PeerId, message_id: i64) {
+    if !matches!(peer.kind(), PeerKind::Channel) {
+        index_private(message_id, peer.bare_id_unchecked());
+    }
+}
+
+/// Remember which private chat or basic group a message belongs to.
+pub fn index_private(message_id: i64, chat_id: i64) {
+    let mut cache = CACHE.lock().unwrap();
+    if cache.private.insert(message_id, chat_id).is_none() {
+        cache.private_order.push_back(message_id);
+    }
+    while cache.private.len() > cache.capacity {
+        let Some(oldest) = cache.private_order.pop_front() else {
+            break;
+        };
+        cache.private.remove(&oldest);
+    }
+}
+
+/// Chat of a private chat or basic group message. Every message is indexed when
+/// it is pushed into a buffer and when the cache is warmed, so this covers
+/// buffered and recently stored ones; storage is not searched, chats_log has no
+/// index by message id alone.
+pub fn private_chat(message_id: i64) -> Option<i64> {
+    CACHE.lock().unwrap().private.get(&message_id).copied()
+}
+
+/// Load the most recent messages from storage, with their latest edits, and
+/// index those of private chats and basic groups for `private_chat`.
+pub async fn warm() {
+    let capacity = CACHE.lock().unwrap().capacity;
+    let recent = match storage().recent_messages(capacity).await {
+        Ok(recent) => recent,
+        Err(e) => {
+            warn!("failed to warm message cache: {e}");
+            return;
+        }
+    };
+    let since = recent.iter().map(|m| m.date_time).min().unwrap_or(0);
+    let edits = storage().recent_edits(since).await.unwrap_or_else(|e| {
+        warn!("failed to load edits for message cache: {e}");
+        Vec::new()
+    });
+
+    // Stored chat ids are bare; a channel is known to peer_cache by its Bot API
+    // id. Chats that can't be looked up are not indexed, a wrong chat is worse
+    // than none.
+    let mut private: HashMap<i64, bool> = HashMap::new();
+    for m in &recent {
+        if !private.contains_key(&m.chat_id) {
+            let channel = storage().peer(Some(CHANNEL_ID_OFFSET - m.chat_id)).await;
+            private.insert(m.chat_id, matches!(channel, Ok(None)));
+        }
+    }
+
+    let count = recent.len();
+    for m in recent {
+        if private[&m.chat_id] {
+            index_private(m.message_id, m.chat_id);
+        }
+        insert_if_absent(
+            m.chat_id,
+            m.message_id,
+            CachedMessage {
+                text: m.message,
+                chat_title: m.chat_title,
+                first_name: m.first_name,
+                second_name: m.second_name,
+                user_id: m.user_id,
+                date_time: m.date_time,
+                media: m.media,
+                media_id: m.media_id,
+                outgoing: m.outgoing,
+            },
+        );
+    }
+    for e in edits {
+        update_text(e.chat_id, e.message_id, &e.message);
+        update_media(e.chat_id, e.message_id, &e.media, e.media_id);
+    }
+    info!("message cache warmed with {count} messages");
+}
+
+#[cfg(test)]
+mod tests {
+    use clickhouse::Row;
+    use serde::Serialize;
+
+    use crate::storage::{CHANNEL_ID_OFFSET, PeerRow, Storage, storage};
+
+    #[derive(Row, Serialize)]
+    struct Stored {
+        date_time: u32,
+        chat_id: i64,
+        message_id: i64,
+        message: String,
+    }
+
+    fn stored(chat_id: i64, message_id: i64) -> Stored {
+        Stored {
+            date_time: 1_700_000_000,
+            chat_id,
+            message_id,
+            message: "hi".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn warm_indexes_private_chats_but_not_channels() {
+        let peer = PeerRow {
+            peer_id: CHANNEL_ID_OFFSET - 9402,
+            hash: Some(1),
+            subtype: None,
+        };
+        storage().insert("peer_cache", &[peer]).await.unwrap();
+        storage()
+            .insert("chats_log", &[stored(9401, 940_101), stored(9402, 940_201)])
+            .await
+            .unwrap();
+
+        super::warm().await;
+        assert_eq!(super::private_chat(940_101), Some(9401));
+        assert_eq!(super::private_chat(940_201), None);
+        assert!(super::contains(9402, 940_201));
+    }
+}
@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+
+use clickhouse::Row;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::Result;
+use crate::db::clickhouse;
+
+/// Every migration shipped with the binary, in the order it is applied.
+/// New files in `migrations/` must be appended here.
+const MIGRATIONS: &[(&str, &str)] = &[
+    (
+        "legacy/001_create_admin_actions2",
+        include_str!("../migrations/legacy/001_create_admin_actions2.sql"),
+    ),
+    (
+        "legacy/002_create_chats_log",
+        include_str!("../migrations/legacy/002_create_chats_log.sql"),
+    ),
+    (
+        "legacy/003_create_deleted_log",
+        include_str!("../migrations/legacy/003_create_deleted_log.sql"),
+    ),
+    (
+        "legacy/004_create_telegram_messages_new",
+        include_str!("../migrations/legacy/004_create_telegram_messages_new.sql"),
+    ),
+    (
+        "legacy/005_create_user_sessions",
+        include_str!("../migrations/legacy/005_create_user_sessions.sql"),
+    ),
+    (
+        "legacy/006_create_materialized_views",
+        include_str!("../migrations/legacy/006_create_materialized_views.sql"),
+    ),
+    (
+        "legacy/007_remove_nullable_columns",
+        include_str!("../migrations/legacy/007_remove_nullable_columns.sql"),
+    ),
+    (
+        "legacy/008__add_mv_my_messages_to_chats_log",
+        include_str!("../migrations/legacy/008__add_mv_my_messages_to_chats_log.sql"),
+    ),
+    (
+        "legacy/009_fix_sort_columns",
+        include_str!("../migrations/legacy/009_fix_sort_columns.sql"),
+    ),
+    (
+        "legacy/010_add_log_output_to_admin_actions2",
+        include_str!("../migrations/legacy/010_add_log_output_to_admin_actions2.sql"),
+    ),
+    (
+        "legacy/011_create_edited_log",
+        include_str!("../migrations/legacy/011_create_edited_log.sql"),
+    ),
+    (
+        "legacy/012_add_user_id_to_edited_log",
+        include_str!("../migrations/legacy/012_add_user_id_to_edited_log.sql"),
+    ),
+    (
+        "legacy/013_ad_edit_log_hm_view",
+        include_str!("../migrations/legacy/013_ad_edit_log_hm_view.sql"),
+    ),
+    (
+        "legacy/014_add_delete_log_hr_view",
+        include_str!("../migrations/legacy/014_add_delete_log_hr_view.sql"),
+    ),
+    (
+        "legacy/015_alter_admin_actions2_user_title_low_cardinality",
+        include_str!("../migrations/legacy/015_alter_admin_actions2_user_title_low_cardinality.sql"),
+    ),
+    (
+        "legacy/016_alter_admin_actions2_usernames_low_cardinality",
+        include_str!("../migrations/legacy/016_alter_admin_actions2_usernames_low_cardinality.sql"),
+    ),
+    (
+        "legacy/017_add_original_message_to_edited_log",
+        include_str!("../migrations/legacy/017_add_original_message_to_edited_log.sql"),
+    ),
+    (
+        "legacy/018_edit_log_chain_statistic",
+        include_str!("../migrations/legacy/018_edit_log_chain_statistic.sql"),
+    ),
+    (
+        "legacy/019_add_export_table",
+        include_str!("../migrations/legacy/019_add_export_table.sql"),
+    ),
+    (
+        "legacy/020_create_reactions_log",
+        include_str!("../migrations/legacy/020_create_reactions_log.sql"),
+    ),
+    (
+        "legacy/021_alter_reactions_log_remove_user_id",
+        include_str!("../migrations/legacy/021_alter_reactions_log_remove_user_id.sql"),
+    ),
+    (
+        "001_add_topic_to_telegram_messages_new",
+        include_str!("../migrations/001_add_topic_to_telegram_messages_new.sql"),
+    ),
+    (
+        "002_drop_topic_columns",
+        include_str!("../migrations/002_drop_topic_columns.sql"),
+    ),
+    (
+        "003_create_peer_cache",
+        include_str!("../migrations/003_create_peer_cache.sql"),
+    ),
+    (
+        "004_add_community_tag_to_chats_log",
+        include_str!("../migrations/004_add_community_tag_to_chats_log.sql"),
+    ),
+    (
+        "005_create_session_tables",
+        include_str!("../migrations/005_create_session_tables.sql"),
+    ),
+    (
+        "006_community_tag_low_cardinality",
+        include_str!("../migrations/006_community_tag_low_cardinality.sql"),
+    ),
+    (
+        "007_create_join_requests_log",
+        include_str!("../migrations/007_create_join_requests_log.sql"),
+    ),
+    (
+        "008_drop_join_requests_user_columns",
+        include_str!("../migrations/008_drop_join_requests_user_columns.sql"),
+    ),
+    (
+        "009_create_media_log",
+        include_str!("../migrations/009_create_media_log.sql"),
+    ),
+    (
+        "010_add_raw_to_chats_log",
+        include_str!("../migrations/010_add_raw_to_chats_log.sql"),
+    ),
+    (
+        "011_add_deleted_snapshot_and_media",
+        include_str!("../migrations/011_add_deleted_snapshot_and_media.sql"),
+    ),
+    (
+        "012_add_topic_columns",
+        include_str!("../migrations/012_add_topic_columns.sql"),
+    ),
+    (
+        "013_add_forward_columns",
+        include_str!("../migrations/013_add_forward_columns.sql"),
+    ),
+    (
+        "014_add_grouped_id",
+        include_str!("../migrations/014_add_grouped_id.sql"),
+    ),
+    (
+        "015_add_outgoing_to_edit_logs",
+        include_str!("../migrations/015_add_outgoing_to_edit_logs.sql"),
+    ),
+    (
+        "016_add_original_unknown",
+        include_str!("../migrations/016_add_original_unknown.sql"),
+    ),
+    (
+        "017_add_media_ids",
+        include_str!("../migrations/017_add_media_ids.sql"),
+    ),
+    (
+        "018_create_backfill_cursors",
+        include_str!("../migrations/018_create_backfill_cursors.sql"),
+    ),
+];
+
+/// Last migration that existed before this runner did. Databases set up by hand
+/// have at most these applied; anything after it is always run.
+const BASELINE: &str = "008_drop_join_requests_user_columns";
+
+const CREATE_SCHEMA_MIGRATIONS: &str = "\
+    CREATE TABLE IF NOT EXISTS schema_migrations (\
+        version    String, \
+        checksum   String, \
+        applied_at DateTime DEFAULT now()\
+    ) ENGINE = ReplacingMergeTree(applied_at) \
+    ORDER BY version";
+
+#[derive(Row, Serialize, Deserialize)]
+struct MigrationRow {
+    version: String,
+    checksum: String,
+}
+
+/// Apply every pending migration in order, recording each one in `schema_migrations`.
+///
+/// Fails if an already applied migration was edited afterwards (checksum mismatch).
+/// A database that already has the bot tables but no migration history (set up by
+/// hand before the runner existed) is baselined: migrations up to `BASELINE` are
+/// recorded as applied without being executed, later ones are applied as usual.
+pub async fn run() -> Result<()> {
+    clickhouse().query(CREATE_SCHEMA_MIGRATIONS).execute().await?;
+
+    let mut applied: HashMap<String, String> = clickhouse()
+        .query("SELECT version, checksum FROM schema_migrations FINAL")
+        .fetch_all::<MigrationRow>()
+        .await?
+        .into_iter()
+        .map(|r| (r.version, r.checksum))
+        .collect();
+
+    if applied.is_empty() && table_exists("chats_log").await? {
+        warn!("schema_migrations is empty but chats_log exists, baselining up to {BASELINE}");
+        let end = MIGRATIONS
+            .iter()
+            .position(|(version, _)| *version == BASELINE)
+            .expect("BASELINE is not in MIGRATIONS");
+        for (version, sql) in &MIGRATIONS[..=end] {
+            let checksum = checksum(sql);
+            record(version, &checksum).await?;
+            applied.insert(version.to_string(), checksum);
+        }
+    }
+
+    let mut count = 0;
+    for (version, sql) in MIGRATIONS {
+        let checksum = checksum(sql);
+        match applied.get(*version) {
+            Some(c) if *c == checksum => continue,
+            Some(c) => {
+                return Err(format!(
+                    "migration {version} was modified after being applied (checksum {c} -> {checksum})"
+                )
+                .into());
+            }
+            None => {}
+        }
+        apply(sql)
+            .await
+            .map_err(|e| format!("migration {version} failed: {e}"))?;
+        record(version, &checksum).await?;
+        info!("applied migration {version}");
+        count += 1;
+    }
+
+    if count > 0 {
+        info!("applied {count} migration(s)");
+    }
+    Ok(())
+}
+
+async fn table_exists(name: &str) -> Result<bool> {
+    let count: u64 = clickhouse()
+        .query("SELECT count() FROM system.tables WHERE database = currentDatabase() AND name = ?")
+        .bind(name)
+        .fetch_one()
+        .await?;
+    Ok(count > 0)
+}
+
+async fn record(version: &str, checksum: &str) -> Result<()> {
+    let mut insert = clickhouse().insert::<MigrationRow>("schema_migrations").await?;
+    insert
+        .write(&MigrationRow {
+            version: version.to_string(),
+            checksum: checksum.to_string(),
+        })
+        .await?;
+    insert.end().await?;
+    Ok(())
+}
+
+/// Execute a migration file statement by statement. The HTTP interface accepts one
+/// statement per request, so `SET` statements are turned into client options that
+/// apply to the rest of the file.
+async fn apply(sql: &str) -> Result<()> {
+    let mut client = clickhouse().clone();
+    for statement in statements(sql) {
+        if let Some((name, value)) = parse_set(statement) {
+            client = client.with_option(name, value);
+            continue;
+        }
+        client.query(statement).execute().await?;
+    }
+    Ok(())
+}
+
+/// Split a migration file on `;`, skipping semicolons inside quotes and
+/// comments. Leading comments are cut off each statement, and statements that
+/// are nothing but comments are dropped.
+fn statements(sql: &str) -> Vec<&str> {
+    let bytes = sql.as_bytes();
+    let mut statements = Vec::new();
+    // First byte of code in the current statement.
+    let mut start = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i = sql[i..].find('\n').map_or(bytes.len(), |n| i + n);
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = sql[i + 2..].find("*/").map_or(bytes.len(), |n| i + n + 4);
+            }
+            b';' => {
+                if let Some(s) = start.take() {
+                    statements.push(sql[s..i].trim());
+                }
+                i += 1;
+            }
+            quote @ (b'\'' | b'"' | b'`') => {
+                start.get_or_insert(i);
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    // Skip the escaped character.
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                i += 1;
+            }
+            c => {
+                if !c.is_ascii_whitespace() {
+                    start.get_or_insert(i);
+                }
+                i += 1;
+            }
+        }
+    }
+    if let Some(s) = start {
+        statements.push(sql[s..].trim());
+    }
+    statements
+}
+
+fn parse_set(statement: &str) -> Option<(&str, &str)> {
+    let rest = statement
+        .get(..4)
+        .filter(|p| p.eq_ignore_ascii_case("SET "))
+        .map(|_| &statement[4..])?;
+    let (name, value) = rest.split_once('=')?;
+    Some((name.trim(), value.trim().trim_matches('\'')))
+}
+
+fn checksum(sql: &str) -> String {
+    format!("{:x}", Sha256::digest(sql.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MIGRATIONS, parse_set, statements};
+
+    #[test]
+    fn splits_on_semicolons_outside_quotes_and_comments() {
+        let sql = "-- header; not a statement\n\
+                   SET a = 1;\n\
+                   /* block; comment */ CREATE TABLE t (s String DEFAULT ';', `c;d` UInt8);\n\
+                   INSERT INTO t VALUES ('it''s; fine', 'back\\'slash;');\n\
+                   -- trailing comment only\n";
+        assert_eq!(
+            statements(sql),
+            [
+                "SET a = 1",
+                "CREATE TABLE t (s String DEFAULT ';', `c;d` UInt8)",
+                "INSERT INTO t VALUES ('it''s; fine', 'back\\'slash;')",
+            ]
+        );
+        assert_eq!(statements("SELECT 1"), ["SELECT 1"]);
+        assert!(statements("  -- nothing\n;\n/* here */").is_empty());
+    }
+
+    #[test]
+    fn parses_set_statements() {
+        assert_eq!(
+            parse_set("SET allow_suspicious_low_cardinality_types = 1"),
+            Some(("allow_suspicious_low_cardinality_types", "1"))
+        );
+        assert_eq!(parse_set("set mode='strict'"), Some(("mode", "strict")));
+        assert_eq!(parse_set("SETTINGS x = 1"), None);
+        assert_eq!(parse_set("SET"), None);
+        assert_eq!(parse_set("SELECT 1"), None);
+    }
+
+    #[test]
+    fn every_shipped_migration_has_statements() {
+        for (version, sql) in MIGRATIONS {
+            let statements = statements(sql);
+            assert!(!statements.is_empty(), "{version} has no statements");
+            assert!(
+                statements.iter().all(|s| !s.is_empty() && !s.starts_with("--")),
+                "{version} splits badly"
+            );
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{LazyLock, OnceLock};
+use std::time::Duration;
+
+use grammers_client::Client;
+use grammers_session::types::PeerRef;
+use grammers_tl_types as tl;
+use log::{error, info, warn};
+use tokio::sync::mpsc;
+
+use crate::utils::text::truncate_message;
+
+/// Posts deleted and edited messages from opted-in chats to Telegram, so they
+/// are seen without watching the console.
+///
+/// `NOTIFY_CHATS` (comma-separated chat ids, Bot API form) enables it for those
+/// chats. Cards go to Saved Messages unless `NOTIFY_TARGET` names another chat,
+/// e.g. a private channel as `-100…`.
+/// Events are collected for `NOTIFY_WINDOW_SECS` (default 5); a chat with more
+/// than `NOTIFY_BURST` (default 5) events of one kind in a window gets a single
+/// summary instead of one card each.
+static CHATS: LazyLock<HashSet<i64>> = LazyLock::new(|| {
+    std::env::var("NOTIFY_CHATS")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|s| s.trim().parse().ok())
+        .map(crate::utils::peer::bare_id)
+        .collect()
+});
+
+static TARGET: LazyLock<Option<i64>> = LazyLock::new(|| {
+    match std::env::var("NOTIFY_TARGET").as_deref() {
+        Err(_) | Ok("") | Ok("me") => None,
+        Ok(v) => Some(v.trim().parse().expect("NOTIFY_TARGET invalid")),
+    }
+});
+
+static WINDOW: LazyLock<Duration> = LazyLock::new(|| {
+    Duration::from_secs(
+        std::env::var("NOTIFY_WINDOW_SECS")
+            .map(|v| v.parse().expect("NOTIFY_WINDOW_SECS invalid"))
+            .unwrap_or(5),
+    )
+});
+
+static BURST: LazyLock<usize> = LazyLock::new(|| {
+    std::env::var("NOTIFY_BURST")
+        .map(|v| v.parse().expect("NOTIFY_BURST invalid"))
+        .unwrap_or(5)
+});
+
+/// Pause between two sent messages, to stay clear of flood waits.
+const SEND_GAP: Duration = Duration::from_secs(1);
+/// Messages listed in a summary before "and N more".
+const SUMMARY_LINES: usize = 10;
+
+static QUEUE: OnceLock<mpsc::UnboundedSender<Notice>> = OnceLock::new();
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Deleted,
+    Edited,
+}
+
+pub struct Notice {
+    pub kind: Kind,
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub chat_title: String,
+    pub sender: String,
+    /// Original text for deletions, unified diff for edits.
+    pub body: String,
+}
+
+/// Start the sender task if any chat opted in.
+pub fn start(client: Client) {
+    if CHATS.is_empty() {
+        return;
+    }
+    let (tx, rx) = mpsc::unbounded_channel();
+    if QUEUE.set(tx).is_err() {
+        return;
+    }
+    info!("notifying about {} chats", CHATS.len());
+    tokio::spawn(run(client, rx));
+}
+
+/// Queue a notice; ignored unless its chat opted in.
+pub fn notify(notice: Notice) {
+    if !CHATS.contains(&notice.chat_id) {
+        return;
+    }
+    if let Some(queue) = QUEUE.get() {
+        let _ = queue.send(notice);
+    }
+}
+
+async fn run(client: Client, mut rx: mpsc::UnboundedReceiver<Notice>) {
+    let target = loop {
+        match resolve_target(&client).await {
+            Ok(target) => break target,
+            Err(e) => {
+                error!("cannot resolve notify target: {e}, retrying in a minute");
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
+        }
+    };
+
+    while let Some(first) = rx.recv().await {
+        let deadline = tokio::time::Instant::now() + *WINDOW;
+        let mut batch = vec![first];
+        while let Ok(Some(notice)) = tokio::time::timeout_at(deadline, rx.recv()).await {
+            batch.push(notice);
+        }
+
+        let mut groups: BTreeMap<(i64, Kind), Vec<Notice>> = BTreeMap::new();
+        for notice in batch {
+            groups
+                .entry((notice.chat_id, notice.kind))
+                .or_default()
+                .push(notice);
+        }
+
+        for notices in groups.into_values() {
+            let texts = if notices.len() > *BURST {
+                vec![summary(&notices)]
+            } else {
+                notices.iter().map(card).collect()
+            };
+            for text in texts {
+                if let Err(e) = client.send_message(target, truncate_message(text)).await {
+                    warn!("failed to send notification: {e}");
+                }
+                tokio::time::sleep(SEND_GAP).await;
+            }
+        }
+    }
+}
+
+async fn resolve_target(client: &Client) -> Result<PeerRef, Box<dyn std::error::Error>> {
+    let Some(target) = *TARGET else {
+        let peer = client.resolve_peer(tl::enums::InputPeer::PeerSelf).await?;
+        return peer
+            .to_ref()
+            .await?
+            .ok_or_else(|| "no access hash for notify target".into());
+    };
+    Ok(crate::utils::peer::resolve(client, target).await?)
+}
+
+fn header(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Deleted => "🗑 deleted",
+        Kind::Edited => "✏️ edited",
+    }
+}
+
+fn chat_display(notice: &Notice) -> String {
+    if notice.chat_title.is_empty() {
+        notice.chat_id.to_string()
+    } else {
+        format!("{} ({})", notice.chat_title, notice.chat_id)
+    }
+}
+
+fn card(notice: &Notice) -> String {
+    let mut text = format!("{} in {}\n#{}", header(notice.kind), chat_display(notice), notice.message_id);
+    if !notice.sender.is_empty() {
+        text.push_str(&format!(" from {}", notice.sender));
+    }
+    text.push_str("\n\n");
+    text.push_str(if notice.body.is_empty() { "[unknown content]" } else { &notice.body });
+    text
+}
+
+fn summary(notices: &[Notice]) -> String {
+    let first = &notices[0];
+    let mut text = format!("{} {} messages in {}\n", header(first.kind), notices.len(), chat_display(first));
+    for notice in notices.iter().take(SUMMARY_LINES) {
+        let preview: String = notice
+            .body
+            .lines()
+            .find(|l| !l.is_empty() && !l.starts_with("@@"))
+            .unwrap_or_default()
+            .chars()
+            .take(80)
+            .collect();
+        text.push_str(&format!("\n#{}", notice.message_id));
+        if !notice.sender.is_empty() {
+            text.push_str(&format!(" {}:", notice.sender));
+        }
+        text.push(' ');
+        text.push_str(&preview);
+    }
+    if notices.len() > SUMMARY_LINES {
+        text.push_str(&format!("\n…and {} more", notices.len() - SUMMARY_LINES));
+    }
+    text
+}
@@ -4,6 +4,7 @@ use log::{error, info};
 use std::time::Duration;
 
 use crate::db::AdminAction;
+use crate::storage::{Storage, storage};
 
 pub fn start(client: Client, client_id: u64) {
     tokio::spawn(async move {
@@ -69,10 +70,7 @@ fn format_log_output(action: &tl::enums::ChannelAdminLogEventAction, user_title:
             if prev == new {
                 return String::new();
             }
-            let diff = similar::TextDiff::from_lines(&prev, &new)
-                .unified_diff()
-                .missing_newline_hint(false)
-                .to_string();
+            let diff = crate::utils::diff::unified_diff(&prev, &new);
             crate::utils::diff::colorize_unified_diff(&diff, &prev, &new)
         }
         DeleteMessage(a) => message_text(&a.message),
@@ -175,12 +173,7 @@ async fn resolve_channel(
 async fn get_last_event_id(
     chat_id: u64,
 ) -> Result<u64, Box<dyn std::error::Error>> {
-    let max_id: u64 = crate::db::clickhouse()
-        .query("SELECT max(event_id) FROM admin_actions2 WHERE chat_id = ?")
-        .bind(chat_id)
-        .fetch_one()
-        .await
-        .unwrap_or(0);
+    let max_id = storage().last_admin_event_id(chat_id).await.unwrap_or(0);
     Ok(max_id)
 }
 
@@ -188,8 +181,6 @@ async fn log_admin_actions(
     client: &Client,
     _client_id: u64,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let ch = crate::db::clickhouse();
-
     let chat_ids_str = std::env::var("TELEGRAM_CHAT_IDS")?;
     let chat_ids: Vec<i64> = chat_ids_str
         .split(',')
@@ -256,14 +247,14 @@ async fn log_admin_actions(
                 break;
             }
 
-            let mut insert = ch.insert::<AdminAction>("admin_actions2").await?;
+            let mut rows = Vec::with_capacity(result.events.len());
 
             for event in &result.events {
                 let tl::enums::ChannelAdminLogEvent::Event(ev) = event;
 
                 let (user_title, usernames) = extract_user_info(&result.users, ev.user_id);
 
-                let log = &AdminAction {
+                let log = AdminAction {
                     date: ev.date as u32,
                     event_id: ev.id as u64,
                     chat_id: chat_id_u64,
@@ -286,12 +277,10 @@ async fn log_admin_actions(
                     log.log_output,
                 );
 
-                insert
-                    .write(log)
-                    .await?;
+                rows.push(log);
             }
 
-            insert.end().await?;
+            storage().insert("admin_actions2", &rows).await?;
 
             let (batch_min, batch_max) = result.events.iter().fold((i64::MAX, 0u64), |(min, max), e| {
                 let tl::enums::ChannelAdminLogEvent::Event(ev) = e;
@@ -2,21 +2,81 @@ use std::time::Duration;
 
 use crate::db;
 
+/// Flush everything, including batches still waiting out a retry backoff.
+/// Called on shutdown.
 pub async fn flush_all() {
+    let incoming = db::INCOMING_BUF.drain().await;
+    let outgoing = db::OUTGOING_BUF.drain().await;
+    let edited = db::EDITED_BUF.drain().await;
+    let deleted = db::DELETED_BUF.drain().await;
+    let reactions = db::REACTIONS_BUF.drain().await;
+    let media = db::MEDIA_BUF.drain().await;
+    if incoming + outgoing + edited + deleted + reactions + media > 0 {
+        log::info!("flushed incoming: {incoming}, outgoing: {outgoing}, edited: {edited}, deleted: {deleted}, reactions: {reactions}, media: {media}");
+    }
+}
+
+/// Insert spool batches left over from a previous run. Called once at startup:
+/// otherwise they wait until their buffer next becomes due, which on a quiet
+/// chat can take arbitrarily long.
+pub async fn replay_spool() {
+    if !crate::spool::enabled() {
+        return;
+    }
     let incoming = db::INCOMING_BUF.flush().await;
+    let outgoing = db::OUTGOING_BUF.flush().await;
     let edited = db::EDITED_BUF.flush().await;
     let deleted = db::DELETED_BUF.flush().await;
-    if incoming + edited + deleted > 0 {
-        log::info!("flushed incoming: {incoming}, edited: {edited}, deleted: {deleted}");
+    let reactions = db::REACTIONS_BUF.flush().await;
+    let media = db::MEDIA_BUF.flush().await;
+    if incoming + outgoing + edited + deleted + reactions + media > 0 {
+        log::info!("replayed spool incoming: {incoming}, outgoing: {outgoing}, edited: {edited}, deleted: {deleted}, reactions: {reactions}, media: {media}");
+    }
+}
+
+/// Flush only the buffers whose row, size or age threshold is crossed.
+async fn flush_due() {
+    let mut incoming = 0;
+    let mut outgoing = 0;
+    let mut edited = 0;
+    let mut deleted = 0;
+    let mut reactions = 0;
+    let mut media = 0;
+    if db::INCOMING_BUF.is_due().await {
+        incoming = db::INCOMING_BUF.flush().await;
+    }
+    if db::OUTGOING_BUF.is_due().await {
+        outgoing = db::OUTGOING_BUF.flush().await;
+    }
+    if db::EDITED_BUF.is_due().await {
+        edited = db::EDITED_BUF.flush().await;
+    }
+    if db::DELETED_BUF.is_due().await {
+        deleted = db::DELETED_BUF.flush().await;
+    }
+    if db::REACTIONS_BUF.is_due().await {
+        reactions = db::REACTIONS_BUF.flush().await;
+    }
+    if db::MEDIA_BUF.is_due().await {
+        media = db::MEDIA_BUF.flush().await;
+    }
+    if incoming + outgoing + edited + deleted + reactions + media > 0 {
+        log::info!("flushed incoming: {incoming}, outgoing: {outgoing}, edited: {edited}, deleted: {deleted}, reactions: {reactions}, media: {media}");
     }
 }
 
 pub fn start() {
     tokio::spawn(async {
-        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
         loop {
-            interval.tick().await;
-            flush_all().await;
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = db::flush_requested() => {}
+            }
+            flush_due().await;
+            if crate::spool::enabled() {
+                let _ = tokio::task::spawn_blocking(crate::spool::sync_all).await;
+            }
         }
     });
 }
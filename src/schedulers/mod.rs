@@ -2,7 +2,7 @@ mod user_sessions;
 mod admin_actions;
 mod flush_buffers;
 
-pub use flush_buffers::flush_all;
+pub use flush_buffers::{flush_all, replay_spool};
 
 use grammers_client::Client;
 
@@ -11,3 +11,9 @@ pub fn start(client: Client, client_id: u64) {
     admin_actions::start(client, client_id);
     flush_buffers::start();
 }
+
+/// Only the buffer flusher, for one-shot runs like `backfill` and `import` that
+/// fill the buffers without the update loop.
+pub fn start_flusher() {
+    flush_buffers::start();
+}
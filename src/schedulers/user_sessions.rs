@@ -4,6 +4,7 @@ use log::error;
 use std::time::Duration;
 
 use crate::db::TelegramSession;
+use crate::storage::{Storage, storage};
 
 pub fn start(client: Client, client_id: u64) {
     tokio::spawn(async move {
@@ -22,7 +23,7 @@ async fn log_sessions(client: &Client, client_id: u64) -> Result<(), Box<dyn std
         .invoke(&tl::functions::account::GetAuthorizations {})
         .await?;
 
-    let mut insert = crate::db::clickhouse().insert::<TelegramSession>("user_sessions").await?;
+    let mut rows = Vec::new();
     for auth in &result.authorizations {
         let tl::enums::Authorization::Authorization(session) = auth;
 
@@ -30,7 +31,7 @@ async fn log_sessions(client: &Client, client_id: u64) -> Result<(), Box<dyn std
             continue;
         }
 
-        insert.write(&TelegramSession {
+        rows.push(TelegramSession {
             hash: session.hash,
             device_model: session.device_model.clone(),
             platform: session.platform.clone(),
@@ -47,10 +48,9 @@ async fn log_sessions(client: &Client, client_id: u64) -> Result<(), Box<dyn std
                 .unwrap()
                 .as_secs() as u32,
             client_id,
-        }).await?;
-
+        });
     }
-    insert.end().await?;
+    storage().insert("user_sessions", &rows).await?;
 
     Ok(())
 }
@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use chrono::{NaiveDate, TimeZone};
+
+use crate::commands::{Command, Context, Reply};
+use crate::storage::{CHANNEL_ID_OFFSET, SearchQuery, Storage, storage};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Results per page.
+const PAGE_SIZE: usize = 10;
+/// Deepest page; also keeps the offset far from overflowing.
+const MAX_PAGE: usize = 1_000;
+/// Characters of each message shown.
+const PREVIEW_CHARS: usize = 300;
+
+static TZ: LazyLock<chrono_tz::Tz> = LazyLock::new(|| {
+    std::env::var("TZ")
+        .ok()
+        .and_then(|tz| tz.parse().ok())
+        .unwrap_or(chrono_tz::UTC)
+});
+
+pub const USAGE: &str = "<words | \"phrase\"> [re:<regex>] [chat:<id>] [user:<id>] \
+                         [from:<YYYY-MM-DD>] [to:<YYYY-MM-DD>] [page:<n>]";
+
+pub const COMMAND: Command = Command {
+    name: "search",
+    usage: USAGE,
+    about: "search stored messages, edits included",
+    run,
+};
+
+fn run<'a>(_ctx: &'a Context, args: &'a str) -> Reply<'a> {
+    Box::pin(search(args))
+}
+
+/// Run a search written in the query syntax and format one page of results.
+pub async fn search(input: &str) -> Result<String, Error> {
+    let (query, page) = parse(input)?;
+    let mut hits = storage()
+        .search(&query, PAGE_SIZE + 1, (page - 1) * PAGE_SIZE)
+        .await?;
+    if hits.is_empty() {
+        return Ok(if page == 1 {
+            "nothing found".to_string()
+        } else {
+            format!("no results on page {page}")
+        });
+    }
+    let more = hits.len() > PAGE_SIZE;
+    hits.truncate(PAGE_SIZE);
+
+    // Edits carry no chat title; messages from channels get a link.
+    let mut titles: HashMap<i64, String> = HashMap::new();
+    let mut channels: HashMap<i64, bool> = HashMap::new();
+    for hit in &hits {
+        if !hit.chat_title.is_empty() {
+            titles
+                .entry(hit.chat_id)
+                .or_insert_with(|| hit.chat_title.clone());
+        }
+        if !channels.contains_key(&hit.chat_id) {
+            let channel = storage()
+                .peer(Some(CHANNEL_ID_OFFSET - hit.chat_id))
+                .await?
+                .is_some();
+            channels.insert(hit.chat_id, channel);
+        }
+    }
+
+    let first = (page - 1) * PAGE_SIZE + 1;
+    let mut out = format!("results {}–{}", first, first + hits.len() - 1);
+    for hit in &hits {
+        let title = match titles.get(&hit.chat_id) {
+            Some(title) => title.clone(),
+            None => crate::message_cache::chat_title(hit.chat_id)
+                .unwrap_or_else(|| hit.chat_id.to_string()),
+        };
+        let when = TZ
+            .timestamp_opt(hit.date_time as i64, 0)
+            .single()
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        let who = match (hit.source.as_str(), hit.sender.is_empty()) {
+            ("outgoing", _) => "me".to_string(),
+            (_, true) => hit.user_id.to_string(),
+            (_, false) => hit.sender.clone(),
+        };
+        let mut text: String = hit.message.chars().take(PREVIEW_CHARS).collect();
+        if text.len() < hit.message.len() {
+            text.push('…');
+        }
+
+        out.push_str(&format!("\n\n{when} · {title} · {who}"));
+        if hit.source == "edited" {
+            out.push_str(" · edited");
+        }
+        out.push('\n');
+        out.push_str(&text);
+        if channels.get(&hit.chat_id) == Some(&true) {
+            out.push_str(&format!(
+                "\nhttps://t.me/c/{}/{}",
+                hit.chat_id, hit.message_id
+            ));
+        } else {
+            out.push_str(&format!(
+                "\nchat {} message {}",
+                hit.chat_id, hit.message_id
+            ));
+        }
+    }
+    if more {
+        out.push_str(&format!("\n\nmore: add page:{}", page + 1));
+    }
+    Ok(out)
+}
+
+/// Split the query into words, "quoted phrases" and `key:value` filters.
+fn parse(input: &str) -> Result<(SearchQuery, usize), Error> {
+    let mut query = SearchQuery::default();
+    let mut page = 1;
+    for token in tokenize(input) {
+        let Some((key, value)) = token
+            .split_once(':')
+            .filter(|(k, _)| matches!(*k, "re" | "chat" | "user" | "from" | "to" | "page"))
+        else {
+            query.terms.push(token);
+            continue;
+        };
+        let invalid = || format!("invalid {key}: {value}");
+        match key {
+            "re" => {
+                check_re2(value)?;
+                query.regex = Some(regex::Regex::new(value)?);
+            }
+            "chat" => {
+                let id = value.parse().map_err(|_| invalid())?;
+                query.chat_id = Some(crate::utils::peer::bare_id(id));
+            }
+            "user" => query.user_id = Some(value.parse().map_err(|_| invalid())?),
+            "from" => query.since = Some(day_start(value).ok_or_else(invalid)?),
+            // Inclusive: up to the end of that day.
+            "to" => query.until = Some(day_start(value).ok_or_else(invalid)? + 86_399),
+            _ => {
+                page = value
+                    .parse()
+                    .ok()
+                    .filter(|p| (1..=MAX_PAGE).contains(p))
+                    .ok_or_else(invalid)?
+            }
+        }
+    }
+    if query.terms.is_empty() && query.regex.is_none() {
+        return Err(format!("nothing to search for; usage: {USAGE}").into());
+    }
+    Ok((query, page))
+}
+
+/// Whitespace-separated tokens; double quotes group words, also after `key:`.
+fn tokenize(input: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in input.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn day_start(date: &str) -> Option<u32> {
+    let day = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    let start = TZ
+        .from_local_datetime(&day.and_hms_opt(0, 0, 0)?)
+        .earliest()?;
+    Some(start.timestamp() as u32)
+}
+
+/// Reject `regex` syntax that RE2, which ClickHouse runs, does not know: class
+/// nested classes and set operations, `\<` `\>` `\b{…}` and the `x` and `R` flags.
+/// Both engines lack lookaround and backreferences. One difference remains:
+/// `\d`, `\w` and `\s` are Unicode-aware in Rust but ASCII-only in RE2.
+fn check_re2(pattern: &str) -> Result<(), String> {
+    let unsupported = |what: &str| Err(format!("re: {what} is not supported"));
+    let mut chars = pattern.chars().peekable();
+    let mut in_class = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('<' | '>') if !in_class => return unsupported("\\< or \\>"),
+                Some('b' | 'B') if !in_class && chars.peek() == Some(&'{') => {
+                    return unsupported("\\b{...}");
+                }
+                _ => {}
+            },
+            '[' if !in_class => {
+                in_class = true;
+                // A leading `]` or `^]` is a literal.
+                if chars.peek() == Some(&'^') {
+                    chars.next();
+                }
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                }
+            }
+            // POSIX classes like `[:alpha:]` work in both.
+            '[' if in_class && chars.peek() == Some(&':') => {
+                chars.by_ref().take_while(|&p| p != ']').for_each(drop);
+            }
+            '[' if in_class => return unsupported("a nested class"),
+            ']' if in_class => in_class = false,
+            '&' | '-' | '~' if in_class && chars.peek() == Some(&c) => {
+                return unsupported("a class set operation");
+            }
+            '(' if !in_class && chars.peek() == Some(&'?') => {
+                chars.next();
+                let flags: String = chars
+                    .clone()
+                    .take_while(|f| f.is_ascii_alphabetic() || *f == '-')
+                    .collect();
+                if flags.contains(['x', 'R']) {
+                    return unsupported("the x or R flag");
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_PAGE, check_re2, parse, tokenize};
+
+    #[test]
+    fn tokenize_groups_quoted_words() {
+        assert_eq!(
+            tokenize(r#"  hello "big world"  re:"a b" x"#),
+            ["hello", "big world", "re:a b", "x"]
+        );
+        assert!(tokenize("   ").is_empty());
+    }
+
+    #[test]
+    fn parse_reads_terms_and_filters() {
+        let (query, page) =
+            parse(r#"hello "big world" chat:-1001234567890 user:42 from:2024-03-01 to:2024-03-01 page:3"#)
+                .unwrap();
+        assert_eq!(query.terms, ["hello", "big world"]);
+        assert_eq!(query.chat_id, Some(1_234_567_890));
+        assert_eq!(query.user_id, Some(42));
+        assert_eq!(query.until.unwrap() - query.since.unwrap(), 86_399);
+        assert_eq!(page, 3);
+
+        let (query, page) = parse("re:^h.*o$ cat").unwrap();
+        assert!(query.regex.unwrap().is_match("hello"));
+        assert_eq!(query.terms, ["cat"]);
+        assert_eq!(page, 1);
+    }
+
+    #[test]
+    fn parse_rejects_bad_input() {
+        assert!(parse("").is_err());
+        assert!(parse("chat:42").is_err());
+        assert!(parse("hello chat:abc").is_err());
+        assert!(parse("hello from:2024-13-01").is_err());
+        assert!(parse("hello page:0").is_err());
+        assert!(parse(&format!("hello page:{}", MAX_PAGE + 1)).is_err());
+        assert!(parse(&format!("hello page:{}", usize::MAX)).is_err());
+        assert!(parse("hello re:(a").is_err());
+        // Unknown keys are plain words.
+        assert_eq!(parse("see:this").unwrap().0.terms, ["see:this"]);
+    }
+
+    #[test]
+    fn check_re2_rejects_rust_only_syntax() {
+        for ok in [r"^\d+$", r"[a-z]+", r"[]a]", r"[^]a]", r"[[:alpha:]]+", r"(?i)cat", r"a\[b"] {
+            assert!(check_re2(ok).is_ok(), "{ok}");
+        }
+        for bad in [r"\<word\>", r"\b{start}", r"[a[b]]", r"[a&&b]", r"[a--b]", r"(?x) a"] {
+            assert!(check_re2(bad).is_err(), "{bad}");
+        }
+    }
+}
@@ -5,21 +5,16 @@ use std::env;
 use std::sync::Arc;
 
 use crate::Result;
-use crate::clickhouse_session::ClickhouseSession;
+use crate::storage_session::StorageSession;
 
 pub async fn connect() -> Result<(Client, UpdateStream)> {
-    let api_id = env::var("TG_ID")
-        .expect("TG_ID not set")
-        .parse()
-        .expect("TG_ID invalid");
-
-    let session = Arc::new(ClickhouseSession::open().await?);
+    let session = Arc::new(StorageSession::open().await?);
 
     let SenderPool {
         runner,
         handle,
         updates,
-    } = SenderPool::new(Arc::clone(&session), api_id);
+    } = SenderPool::new(Arc::clone(&session), api_id());
     let client = Client::new(handle);
     let _ = tokio::spawn(runner.run());
 
@@ -31,7 +26,7 @@ pub async fn connect() -> Result<(Client, UpdateStream)> {
         .stream_updates(
             updates,
             UpdatesConfiguration {
-                catch_up: false,
+                catch_up: crate::catch_up::should_catch_up(session.update_state_date()),
                 ..Default::default()
             },
         )
@@ -41,6 +36,30 @@ pub async fn connect() -> Result<(Client, UpdateStream)> {
     Ok((client, updates))
 }
 
+/// Connect for one-off commands that may run next to the bot. No updates are
+/// streamed, so no catch-up starts, and update state is never written, so the
+/// stored one is left to the bot.
+pub async fn connect_without_updates() -> Result<Client> {
+    let session = Arc::new(StorageSession::open().await?.without_update_persistence());
+
+    // Dropping the update channel discards whatever Telegram pushes.
+    let SenderPool { runner, handle, .. } = SenderPool::new(session, api_id());
+    let client = Client::new(handle);
+    let _ = tokio::spawn(runner.run());
+
+    if !client.is_authorized().await? {
+        sign_in(&client).await?;
+    }
+    Ok(client)
+}
+
+fn api_id() -> i32 {
+    env::var("TG_ID")
+        .expect("TG_ID not set")
+        .parse()
+        .expect("TG_ID invalid")
+}
+
 async fn sign_in(client: &Client) -> Result<()> {
     info!("Signing in...");
     let phone: String = dialoguer::Input::new()
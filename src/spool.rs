@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{error, info, warn};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// Write-ahead spool for `WriteBuffer` rows, enabled by setting `SPOOL_DIR`.
+///
+/// Every pushed row is appended to `<table>.jsonl`. A flush seals that file into a
+/// `<table>.<nanos>.batch` file, and a batch is only removed once ClickHouse has
+/// acknowledged its insert, so rows survive crashes and ClickHouse outages.
+///
+/// Appends go to the OS right away and are synced to disk once a second and on
+/// seal, so a power loss can cost the last second of rows. A crash after ClickHouse
+/// acknowledged a batch but before its file was removed replays the batch on the
+/// next start: ReplacingMergeTree tables collapse the duplicates, plain MergeTree
+/// tables such as telegram_messages_new keep them.
+static SPOOL_DIR: LazyLock<Option<PathBuf>> = LazyLock::new(|| {
+    let dir = PathBuf::from(std::env::var("SPOOL_DIR").ok()?);
+    if let Err(e) = fs::create_dir_all(&dir) {
+        error!("cannot create spool dir {}: {e}, spooling disabled", dir.display());
+        return None;
+    }
+    info!("spooling buffered rows to {}", dir.display());
+    Some(dir)
+});
+
+pub fn enabled() -> bool {
+    SPOOL_DIR.is_some()
+}
+
+/// Open active spool file of each table.
+static ACTIVE: LazyLock<Mutex<HashMap<String, File>>> = LazyLock::new(Default::default);
+
+fn active_path(dir: &Path, table: &str) -> PathBuf {
+    dir.join(format!("{table}.jsonl"))
+}
+
+/// Append one serialized row to the table's active spool file.
+pub fn append(table: &str, line: &str) -> io::Result<()> {
+    let Some(dir) = SPOOL_DIR.as_ref() else {
+        return Ok(());
+    };
+    let mut line = line.to_string();
+    line.push('\n');
+    let mut files = ACTIVE.lock().unwrap();
+    let file = match files.entry(table.to_string()) {
+        Entry::Occupied(e) => e.into_mut(),
+        Entry::Vacant(e) => e.insert(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(active_path(dir, table))?,
+        ),
+    };
+    file.write_all(line.as_bytes())
+}
+
+/// Sync every active spool file to disk.
+pub fn sync_all() {
+    for (table, file) in ACTIVE.lock().unwrap().iter() {
+        if let Err(e) = file.sync_data() {
+            error!("spool sync for {table}: {e}");
+        }
+    }
+}
+
+/// Move the active spool file aside as a batch. Rows appended afterwards go to a
+/// fresh active file.
+pub fn seal(table: &str) -> io::Result<Option<PathBuf>> {
+    let Some(dir) = SPOOL_DIR.as_ref() else {
+        return Ok(None);
+    };
+    // Close the handle first, later appends open a fresh active file.
+    if let Some(file) = ACTIVE.lock().unwrap().remove(table) {
+        file.sync_data()?;
+    }
+    let active = active_path(dir, table);
+    if !active.exists() {
+        return Ok(None);
+    }
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let batch = dir.join(format!("{table}.{nanos:020}.batch"));
+    fs::rename(&active, &batch)?;
+    Ok(Some(batch))
+}
+
+/// Sealed batches of the table that are not acknowledged yet, oldest first.
+pub fn batches(table: &str) -> Vec<PathBuf> {
+    let Some(dir) = SPOOL_DIR.as_ref() else {
+        return Vec::new();
+    };
+    let prefix = format!("{table}.");
+    let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with(&prefix) && n.ends_with(".batch"))
+            })
+            .collect(),
+        Err(e) => {
+            error!("cannot list spool dir {}: {e}", dir.display());
+            Vec::new()
+        }
+    };
+    paths.sort();
+    paths
+}
+
+/// Read a batch back. A torn last line from a crash mid-append is skipped.
+pub fn read<T: DeserializeOwned>(path: &Path) -> io::Result<Vec<T>> {
+    let file = fs::File::open(path)?;
+    let mut rows = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(row) => rows.push(row),
+            Err(e) => warn!("skipping malformed spool line in {}: {e}", path.display()),
+        }
+    }
+    Ok(rows)
+}
+
+pub fn remove(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        error!("cannot remove spool batch {}: {e}", path.display());
+    }
+}
+
+/// Keep rows ClickHouse will not accept in `<table>.dead.jsonl` for manual replay,
+/// in the spool dir or, without one, in `dead_letter/` under the working directory.
+pub fn dead_letter<T: Serialize>(table: &str, rows: &[T]) {
+    let lines: Vec<String> = rows
+        .iter()
+        .filter_map(|r| serde_json::to_string(r).ok())
+        .collect();
+    let dir = SPOOL_DIR
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("dead_letter"));
+    let path = dir.join(format!("{table}.dead.jsonl"));
+    let mut data = lines.join("\n");
+    data.push('\n');
+    let result = fs::create_dir_all(&dir).and_then(|()| {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut f| f.write_all(data.as_bytes()))
+    });
+    match result {
+        Ok(()) => warn!("moved {} rows to {}", lines.len(), path.display()),
+        Err(e) => {
+            error!("cannot write dead letter {}: {e}", path.display());
+            for line in &lines {
+                error!("dead letter for {table}: {line}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Row {
+        id: u32,
+    }
+
+    #[test]
+    fn read_skips_a_torn_last_line() {
+        let path = std::env::temp_dir().join(format!("spool-test-{}.batch", std::process::id()));
+        std::fs::write(&path, "{\"id\":1}\n\n{\"id\":2}\n{\"id\":").unwrap();
+        let rows = super::read::<Row>(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(rows.unwrap(), [Row { id: 1 }, Row { id: 2 }]);
+    }
+}
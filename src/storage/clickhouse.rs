@@ -0,0 +1,275 @@
+use ::clickhouse::Row;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    BackfillCursor, ChannelStateRow, DcHomeRow, DcOptionRow, MessageContent, PeerRow, RecentEdit,
+    RecentMessage, Result, SearchHit, SearchQuery, SessionSnapshot, Storage, StoredMessage,
+    UpdateStateRow,
+};
+use crate::db::clickhouse;
+
+/// The production backend: tables created by `migrations/`.
+pub struct ClickHouseStorage;
+
+#[derive(Row, Deserialize)]
+struct LastChatRow {
+    title: String,
+    usernames: Vec<String>,
+}
+
+impl Storage for ClickHouseStorage {
+    async fn insert<T>(&self, table: &str, rows: &[T]) -> Result<()>
+    where
+        T: Serialize + Send + Sync,
+        for<'a> T: Row<Value<'a> = T>,
+    {
+        let mut insert = clickhouse().insert::<T>(table).await?;
+        for row in rows {
+            insert.write(row).await?;
+        }
+        insert.end().await?;
+        Ok(())
+    }
+
+    async fn latest_content(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+    ) -> Result<Option<MessageContent>> {
+        Ok(clickhouse()
+            .query(
+                "SELECT message, media, media_id FROM (\
+                    SELECT message, media, media_id, 1 AS p, date_time FROM edited_log WHERE chat_id = ? AND message_id = ? \
+                    UNION ALL \
+                    SELECT message, media, media_id, 2 AS p, date_time FROM chats_log WHERE chat_id = ? AND message_id = ? \
+                    UNION ALL \
+                    SELECT message, media, media_id, 3 AS p, date_time FROM telegram_messages_new WHERE id = ? AND message_id = ?\
+                ) ORDER BY p, date_time DESC LIMIT 1",
+            )
+            .bind(chat_id)
+            .bind(message_id)
+            .bind(chat_id)
+            .bind(message_id)
+            .bind(chat_id)
+            .bind(message_id as u64)
+            .fetch_optional::<MessageContent>()
+            .await?)
+    }
+
+    async fn incoming(&self, chat_id: i64, message_id: i64) -> Result<Option<StoredMessage>> {
+        Ok(clickhouse()
+            .query("SELECT message, chat_title, first_name, second_name, user_id, date_time, media, media_id FROM chats_log WHERE chat_id = ? AND message_id = ? ORDER BY date_time DESC LIMIT 1")
+            .bind(chat_id)
+            .bind(message_id)
+            .fetch_optional::<StoredMessage>()
+            .await?)
+    }
+
+    async fn chat_title(&self, chat_id: i64) -> Result<Option<String>> {
+        Ok(clickhouse()
+            .query("SELECT chat_title FROM chats_log WHERE chat_id = ? ORDER BY date_time DESC LIMIT 1")
+            .bind(chat_id)
+            .fetch_optional::<String>()
+            .await?)
+    }
+
+    async fn outgoing(&self, chat_id: i64, message_id: i64) -> Result<Option<StoredMessage>> {
+        Ok(clickhouse()
+            .query("SELECT message, title AS chat_title, '' AS first_name, '' AS second_name, client_id AS user_id, date_time, media, media_id FROM telegram_messages_new WHERE id = ? AND message_id = ? ORDER BY date_time DESC LIMIT 1")
+            .bind(chat_id)
+            .bind(message_id as u64)
+            .fetch_optional::<StoredMessage>()
+            .await?)
+    }
+
+    async fn outgoing_chat(&self, chat_id: i64) -> Result<Option<(String, Vec<String>)>> {
+        Ok(clickhouse()
+            .query("SELECT title, usernames FROM telegram_messages_new WHERE id = ? AND title != '' ORDER BY date_time DESC LIMIT 1")
+            .bind(chat_id)
+            .fetch_optional::<LastChatRow>()
+            .await?
+            .map(|r| (r.title, r.usernames)))
+    }
+
+    async fn message_exists(&self, chat_id: i64, message_id: i64) -> Result<bool> {
+        let count: u64 = clickhouse()
+            .query(
+                "SELECT sum(c) AS cnt FROM (\
+                    SELECT count() AS c FROM chats_log WHERE chat_id = ? AND message_id = ? \
+                    UNION ALL \
+                    SELECT count() AS c FROM telegram_messages_new WHERE id = ? AND message_id = ?\
+                )",
+            )
+            .bind(chat_id)
+            .bind(message_id)
+            .bind(chat_id)
+            .bind(message_id as u64)
+            .fetch_one()
+            .await?;
+        Ok(count > 0)
+    }
+
+    async fn recent_messages(&self, limit: usize) -> Result<Vec<RecentMessage>> {
+        Ok(clickhouse()
+            .query(
+                "SELECT date_time, chat_id, message_id, message, chat_title, first_name, second_name, \
+                        user_id, media, media_id, outgoing FROM (\
+                    (SELECT date_time, chat_id, message_id, message, chat_title, first_name, second_name, \
+                     user_id, media, media_id, false AS outgoing \
+                     FROM chats_log ORDER BY date_time DESC LIMIT ?) \
+                    UNION ALL \
+                    (SELECT date_time, id AS chat_id, toInt64(message_id) AS message_id, message, title AS chat_title, \
+                     '' AS first_name, '' AS second_name, toUInt64(client_id) AS user_id, media, media_id, true AS outgoing \
+                     FROM telegram_messages_new ORDER BY date_time DESC LIMIT ?)\
+                ) ORDER BY date_time",
+            )
+            .bind(limit as u64)
+            .bind(limit as u64)
+            .fetch_all::<RecentMessage>()
+            .await?)
+    }
+
+    async fn recent_edits(&self, since: u32) -> Result<Vec<RecentEdit>> {
+        Ok(clickhouse()
+            .query(
+                "SELECT chat_id, message_id, argMax(message, date_time) AS message, \
+                 argMax(media, date_time) AS media, argMax(media_id, date_time) AS media_id \
+                 FROM edited_log WHERE date_time >= ? GROUP BY chat_id, message_id",
+            )
+            .bind(since)
+            .fetch_all::<RecentEdit>()
+            .await?)
+    }
+
+    async fn search(
+        &self,
+        query: &SearchQuery,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<SearchHit>> {
+        // Numeric filters are inlined, text ones bound in the order they appear.
+        let mut binds: Vec<&str> = Vec::new();
+        let mut filter = || {
+            let mut conds = Vec::new();
+            for term in &query.terms {
+                conds.push("positionCaseInsensitiveUTF8(message, ?) > 0".to_string());
+                binds.push(term);
+            }
+            if let Some(re) = &query.regex {
+                conds.push("match(message, ?)".to_string());
+                binds.push(re.as_str());
+            }
+            if let Some(chat_id) = query.chat_id {
+                conds.push(format!("chat_id = {chat_id}"));
+            }
+            if let Some(user_id) = query.user_id {
+                conds.push(format!("user_id = {user_id}"));
+            }
+            if let Some(since) = query.since {
+                conds.push(format!("date_time >= {since}"));
+            }
+            if let Some(until) = query.until {
+                conds.push(format!("date_time <= {until}"));
+            }
+            if conds.is_empty() {
+                String::new()
+            } else {
+                format!("WHERE {}", conds.join(" AND "))
+            }
+        };
+        // mv_my_messages_to_chats_log copies our own messages into chats_log with
+        // `user_id = client_id`, so telegram_messages_new is not read here.
+        let sql = format!(
+            "SELECT date_time, chat_id, message_id, chat_title, sender, user_id, message, source FROM (\
+                SELECT date_time, chat_id, message_id, chat_title, \
+                       trimBoth(concat(first_name, ' ', second_name)) AS sender, user_id, message, \
+                       if(user_id = client_id, 'outgoing', 'incoming') AS source \
+                FROM chats_log {} \
+                UNION ALL \
+                SELECT date_time, chat_id, message_id, '' AS chat_title, '' AS sender, \
+                       toUInt64(user_id) AS user_id, message, 'edited' AS source \
+                FROM edited_log {}\
+            ) ORDER BY date_time DESC LIMIT {limit} OFFSET {offset}",
+            filter(),
+            filter(),
+        );
+        let mut q = clickhouse().query(&sql);
+        for value in binds {
+            q = q.bind(value);
+        }
+        Ok(q.fetch_all::<SearchHit>().await?)
+    }
+
+    async fn backfill_cursor(&self, chat_id: i64) -> Result<Option<BackfillCursor>> {
+        // Progress only moves down, so the furthest row is the latest one.
+        Ok(clickhouse()
+            .query(
+                "SELECT chat_id, oldest_id, done FROM backfill_cursors WHERE chat_id = ? \
+                 ORDER BY done DESC, oldest_id ASC LIMIT 1",
+            )
+            .bind(chat_id)
+            .fetch_optional::<BackfillCursor>()
+            .await?)
+    }
+
+    async fn last_admin_event_id(&self, chat_id: u64) -> Result<u64> {
+        Ok(clickhouse()
+            .query("SELECT max(event_id) FROM admin_actions2 WHERE chat_id = ?")
+            .bind(chat_id)
+            .fetch_one()
+            .await?)
+    }
+
+    async fn load_session(&self) -> Result<SessionSnapshot> {
+        let home_dc = clickhouse()
+            .query("SELECT dc_id FROM session_dc_home FINAL WHERE key = 1 LIMIT 1")
+            .fetch_optional::<DcHomeRow>()
+            .await?
+            .map(|r| r.dc_id);
+
+        let dc_options = clickhouse()
+            .query("SELECT dc_id, ipv4, ipv6, auth_key FROM session_dc_option FINAL")
+            .fetch_all::<DcOptionRow>()
+            .await?;
+
+        let update_state = clickhouse()
+            .query(
+                "SELECT pts, qts, date, seq FROM session_update_state FINAL WHERE key = 1 LIMIT 1",
+            )
+            .fetch_optional::<UpdateStateRow>()
+            .await?;
+
+        let channels = clickhouse()
+            .query("SELECT peer_id, pts FROM session_channel_state FINAL")
+            .fetch_all::<ChannelStateRow>()
+            .await?;
+
+        Ok(SessionSnapshot {
+            home_dc,
+            dc_options,
+            update_state,
+            channels,
+        })
+    }
+
+    async fn peer(&self, peer_id: Option<i64>) -> Result<Option<PeerRow>> {
+        let query = match peer_id {
+            Some(id) => clickhouse()
+                .query("SELECT peer_id, hash, subtype FROM peer_cache FINAL WHERE peer_id = ?")
+                .bind(id),
+            None => clickhouse().query(
+                "SELECT peer_id, hash, subtype FROM peer_cache FINAL \
+                 WHERE subtype IS NOT NULL AND bitAnd(subtype, 1) = 1 LIMIT 1",
+            ),
+        };
+        Ok(query.fetch_optional::<PeerRow>().await?)
+    }
+
+    async fn clear_channel_states(&self) -> Result<()> {
+        clickhouse()
+            .query("TRUNCATE TABLE session_channel_state")
+            .execute()
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,699 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use ::clickhouse::Row;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::{
+    BackfillCursor, ChannelStateRow, DcOptionRow, MessageContent, PeerRow, RecentEdit,
+    RecentMessage, Result, SearchHit, SearchQuery, SessionSnapshot, Storage, StoredMessage,
+    UpdateStateRow,
+};
+
+type Record = Map<String, Value>;
+
+/// Keeps every row as JSON in memory; meant for tests and dry runs.
+/// "Latest" means most recently inserted, which matches arrival order.
+#[derive(Default)]
+pub struct MemoryStorage {
+    tables: Mutex<HashMap<String, Vec<Record>>>,
+}
+
+impl MemoryStorage {
+    /// Map the most recently inserted row of `table` matching `filter`.
+    fn latest<R>(
+        &self,
+        table: &str,
+        filter: impl Fn(&Record) -> bool,
+        map: impl FnOnce(&Record) -> R,
+    ) -> Option<R> {
+        let tables = self.tables.lock().unwrap();
+        tables.get(table)?.iter().rev().find(|r| filter(r)).map(map)
+    }
+
+    /// Latest row per key, in first-seen key order.
+    fn latest_per_key<R>(
+        &self,
+        table: &str,
+        key: impl Fn(&Record) -> Option<i64>,
+        map: impl Fn(&Record) -> Option<R>,
+    ) -> Vec<R> {
+        let tables = self.tables.lock().unwrap();
+        let Some(rows) = tables.get(table) else {
+            return Vec::new();
+        };
+        let mut order = Vec::new();
+        let mut latest: HashMap<i64, &Record> = HashMap::new();
+        for row in rows {
+            let Some(k) = key(row) else { continue };
+            if latest.insert(k, row).is_none() {
+                order.push(k);
+            }
+        }
+        order.iter().filter_map(|k| map(latest[k])).collect()
+    }
+}
+
+fn int(row: &Record, key: &str) -> Option<i64> {
+    let v = row.get(key)?;
+    v.as_i64().or_else(|| v.as_u64().map(|u| u as i64))
+}
+
+fn text(row: &Record, key: &str) -> String {
+    row.get(key)
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn is_message(row: &Record, chat_key: &str, chat_id: i64, message_id: i64) -> bool {
+    int(row, chat_key) == Some(chat_id) && int(row, "message_id") == Some(message_id)
+}
+
+impl Storage for MemoryStorage {
+    async fn insert<T>(&self, table: &str, rows: &[T]) -> Result<()>
+    where
+        T: Serialize + Send + Sync,
+        for<'a> T: Row<Value<'a> = T>,
+    {
+        let mut records = Vec::with_capacity(rows.len());
+        for row in rows {
+            if let Value::Object(map) = serde_json::to_value(row)? {
+                records.push(map);
+            }
+        }
+        self.tables
+            .lock()
+            .unwrap()
+            .entry(table.to_string())
+            .or_default()
+            .extend(records);
+        Ok(())
+    }
+
+    async fn latest_content(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+    ) -> Result<Option<MessageContent>> {
+        let filter = |r: &Record| is_message(r, "chat_id", chat_id, message_id);
+        let content = |r: &Record| MessageContent {
+            message: text(r, "message"),
+            media: text(r, "media"),
+            media_id: int(r, "media_id").unwrap_or_default(),
+        };
+        Ok(self
+            .latest("edited_log", filter, content)
+            .or_else(|| self.latest("chats_log", filter, content))
+            .or_else(|| {
+                self.latest(
+                    "telegram_messages_new",
+                    |r| is_message(r, "id", chat_id, message_id),
+                    content,
+                )
+            }))
+    }
+
+    async fn incoming(&self, chat_id: i64, message_id: i64) -> Result<Option<StoredMessage>> {
+        Ok(self.latest(
+            "chats_log",
+            |r| is_message(r, "chat_id", chat_id, message_id),
+            |r| StoredMessage {
+                message: text(r, "message"),
+                chat_title: text(r, "chat_title"),
+                first_name: text(r, "first_name"),
+                second_name: text(r, "second_name"),
+                user_id: int(r, "user_id").unwrap_or_default() as u64,
+                date_time: int(r, "date_time").unwrap_or_default() as u32,
+                media: text(r, "media"),
+                media_id: int(r, "media_id").unwrap_or_default(),
+            },
+        ))
+    }
+
+    async fn chat_title(&self, chat_id: i64) -> Result<Option<String>> {
+        Ok(self.latest(
+            "chats_log",
+            |r| int(r, "chat_id") == Some(chat_id),
+            |r| text(r, "chat_title"),
+        ))
+    }
+
+    async fn outgoing(&self, chat_id: i64, message_id: i64) -> Result<Option<StoredMessage>> {
+        Ok(self.latest(
+            "telegram_messages_new",
+            |r| is_message(r, "id", chat_id, message_id),
+            |r| StoredMessage {
+                message: text(r, "message"),
+                chat_title: text(r, "title"),
+                first_name: String::new(),
+                second_name: String::new(),
+                user_id: int(r, "client_id").unwrap_or_default() as u64,
+                date_time: int(r, "date_time").unwrap_or_default() as u32,
+                media: text(r, "media"),
+                media_id: int(r, "media_id").unwrap_or_default(),
+            },
+        ))
+    }
+
+    async fn outgoing_chat(&self, chat_id: i64) -> Result<Option<(String, Vec<String>)>> {
+        Ok(self.latest(
+            "telegram_messages_new",
+            |r| int(r, "id") == Some(chat_id) && !text(r, "title").is_empty(),
+            |r| {
+                let usernames = r
+                    .get("usernames")
+                    .and_then(|v| serde_json::from_value(v.clone()).ok())
+                    .unwrap_or_default();
+                (text(r, "title"), usernames)
+            },
+        ))
+    }
+
+    async fn message_exists(&self, chat_id: i64, message_id: i64) -> Result<bool> {
+        Ok(self
+            .latest(
+                "chats_log",
+                |r| is_message(r, "chat_id", chat_id, message_id),
+                |_| (),
+            )
+            .or_else(|| {
+                self.latest(
+                    "telegram_messages_new",
+                    |r| is_message(r, "id", chat_id, message_id),
+                    |_| (),
+                )
+            })
+            .is_some())
+    }
+
+    async fn recent_messages(&self, limit: usize) -> Result<Vec<RecentMessage>> {
+        let tables = self.tables.lock().unwrap();
+        let recent =
+            |table: &str, chat_key: &str, title_key: &str, user_key: &str, outgoing: bool| {
+                let rows = tables.get(table).map(Vec::as_slice).unwrap_or_default();
+                rows.iter()
+                    .rev()
+                    .take(limit)
+                    .map(|r| RecentMessage {
+                        date_time: int(r, "date_time").unwrap_or_default() as u32,
+                        chat_id: int(r, chat_key).unwrap_or_default(),
+                        message_id: int(r, "message_id").unwrap_or_default(),
+                        message: text(r, "message"),
+                        chat_title: text(r, title_key),
+                        first_name: text(r, "first_name"),
+                        second_name: text(r, "second_name"),
+                        user_id: int(r, user_key).unwrap_or_default() as u64,
+                        media: text(r, "media"),
+                        media_id: int(r, "media_id").unwrap_or_default(),
+                        outgoing,
+                    })
+                    .collect::<Vec<_>>()
+            };
+        let mut messages = recent("chats_log", "chat_id", "chat_title", "user_id", false);
+        messages.extend(recent(
+            "telegram_messages_new",
+            "id",
+            "title",
+            "client_id",
+            true,
+        ));
+        messages.sort_by_key(|m| m.date_time);
+        Ok(messages)
+    }
+
+    async fn recent_edits(&self, since: u32) -> Result<Vec<RecentEdit>> {
+        let tables = self.tables.lock().unwrap();
+        let mut latest: HashMap<(i64, i64), &Record> = HashMap::new();
+        for r in tables.get("edited_log").into_iter().flatten() {
+            if int(r, "date_time").unwrap_or_default() < since as i64 {
+                continue;
+            }
+            if let (Some(chat_id), Some(message_id)) = (int(r, "chat_id"), int(r, "message_id")) {
+                latest.insert((chat_id, message_id), r);
+            }
+        }
+        Ok(latest
+            .into_iter()
+            .map(|((chat_id, message_id), r)| RecentEdit {
+                chat_id,
+                message_id,
+                message: text(r, "message"),
+                media: text(r, "media"),
+                media_id: int(r, "media_id").unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    async fn search(
+        &self,
+        query: &SearchQuery,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<SearchHit>> {
+        let tables = self.tables.lock().unwrap();
+        let mut hits = Vec::new();
+        let mut scan =
+            |table: &str, chat_key: &str, title_key: &str, user_key: &str, source: &str| {
+                for r in tables.get(table).into_iter().flatten() {
+                    let hit = SearchHit {
+                        date_time: int(r, "date_time").unwrap_or_default() as u32,
+                        chat_id: int(r, chat_key).unwrap_or_default(),
+                        message_id: int(r, "message_id").unwrap_or_default(),
+                        chat_title: text(r, title_key),
+                        sender: format!("{} {}", text(r, "first_name"), text(r, "second_name"))
+                            .trim()
+                            .to_string(),
+                        user_id: int(r, user_key).unwrap_or_default() as u64,
+                        message: text(r, "message"),
+                        source: source.to_string(),
+                    };
+                    if query.matches_row(hit.chat_id, hit.user_id, hit.date_time)
+                        && query.matches_text(&hit.message)
+                    {
+                        hits.push(hit);
+                    }
+                }
+            };
+        scan("chats_log", "chat_id", "chat_title", "user_id", "incoming");
+        scan(
+            "telegram_messages_new",
+            "id",
+            "title",
+            "client_id",
+            "outgoing",
+        );
+        scan("edited_log", "chat_id", "", "user_id", "edited");
+        hits.sort_by_key(|h| std::cmp::Reverse(h.date_time));
+        Ok(hits.into_iter().skip(offset).take(limit).collect())
+    }
+
+    async fn backfill_cursor(&self, chat_id: i64) -> Result<Option<BackfillCursor>> {
+        Ok(self
+            .latest(
+                "backfill_cursors",
+                |r| int(r, "chat_id") == Some(chat_id),
+                |r| serde_json::from_value::<BackfillCursor>(Value::Object(r.clone())).ok(),
+            )
+            .flatten())
+    }
+
+    async fn last_admin_event_id(&self, chat_id: u64) -> Result<u64> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .get("admin_actions2")
+            .into_iter()
+            .flatten()
+            .filter(|r| int(r, "chat_id") == Some(chat_id as i64))
+            .filter_map(|r| int(r, "event_id"))
+            .max()
+            .unwrap_or(0) as u64)
+    }
+
+    async fn load_session(&self) -> Result<SessionSnapshot> {
+        let home_dc = self
+            .latest("session_dc_home", |_| true, |r| int(r, "dc_id"))
+            .flatten()
+            .map(|id| id as i32);
+        let dc_options = self.latest_per_key(
+            "session_dc_option",
+            |r| int(r, "dc_id"),
+            |r| serde_json::from_value::<DcOptionRow>(Value::Object(r.clone())).ok(),
+        );
+        let update_state = self
+            .latest(
+                "session_update_state",
+                |_| true,
+                |r| serde_json::from_value::<UpdateStateRow>(Value::Object(r.clone())).ok(),
+            )
+            .flatten();
+        let channels = self.latest_per_key(
+            "session_channel_state",
+            |r| int(r, "peer_id"),
+            |r| serde_json::from_value::<ChannelStateRow>(Value::Object(r.clone())).ok(),
+        );
+        Ok(SessionSnapshot {
+            home_dc,
+            dc_options,
+            update_state,
+            channels,
+        })
+    }
+
+    async fn peer(&self, peer_id: Option<i64>) -> Result<Option<PeerRow>> {
+        let filter = |r: &Record| match peer_id {
+            Some(id) => int(r, "peer_id") == Some(id),
+            None => int(r, "subtype").is_some_and(|s| s & 1 == 1),
+        };
+        Ok(self
+            .latest("peer_cache", filter, |r| {
+                serde_json::from_value::<PeerRow>(Value::Object(r.clone())).ok()
+            })
+            .flatten())
+    }
+
+    async fn clear_channel_states(&self) -> Result<()> {
+        self.tables.lock().unwrap().remove("session_channel_state");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::clickhouse::Row;
+    use serde::Serialize;
+
+    use super::MemoryStorage;
+    use crate::storage::{
+        BackfillCursor, ChannelStateRow, DcHomeRow, DcOptionRow, PeerRow, SearchQuery, Storage,
+        UpdateStateRow,
+    };
+
+    #[derive(Row, Serialize)]
+    struct Incoming {
+        date_time: u32,
+        chat_id: i64,
+        message_id: i64,
+        message: String,
+        chat_title: String,
+        first_name: String,
+        second_name: String,
+        user_id: u64,
+    }
+
+    #[derive(Row, Serialize)]
+    struct Edited {
+        date_time: u32,
+        chat_id: i64,
+        message_id: i64,
+        message: String,
+        media: String,
+        media_id: i64,
+    }
+
+    #[derive(Row, Serialize)]
+    struct Outgoing {
+        date_time: u32,
+        id: i64,
+        title: String,
+        message_id: u64,
+        message: String,
+        client_id: u64,
+    }
+
+    #[derive(Row, Serialize)]
+    struct AdminAction {
+        chat_id: u64,
+        event_id: u64,
+    }
+
+    fn incoming(chat_id: i64, message_id: i64, message: &str) -> Incoming {
+        Incoming {
+            date_time: 1_700_000_000,
+            chat_id,
+            message_id,
+            message: message.to_string(),
+            chat_title: "chat".to_string(),
+            first_name: "Ann".to_string(),
+            second_name: "Lee".to_string(),
+            user_id: 7,
+        }
+    }
+
+    #[tokio::test]
+    async fn finds_inserted_message() {
+        let s = MemoryStorage::default();
+        assert!(s.incoming(1, 10).await.unwrap().is_none());
+
+        s.insert(
+            "chats_log",
+            &[incoming(1, 10, "hello"), incoming(2, 10, "other chat")],
+        )
+        .await
+        .unwrap();
+        let m = s.incoming(1, 10).await.unwrap().unwrap();
+        assert_eq!(m.message, "hello");
+        assert_eq!(
+            (m.first_name.as_str(), m.second_name.as_str()),
+            ("Ann", "Lee")
+        );
+        assert_eq!(m.user_id, 7);
+        assert_eq!(s.chat_title(1).await.unwrap().as_deref(), Some("chat"));
+        assert_eq!(
+            s.latest_content(1, 10).await.unwrap().unwrap().message,
+            "hello"
+        );
+    }
+
+    #[tokio::test]
+    async fn latest_content_prefers_last_edit() {
+        let s = MemoryStorage::default();
+        s.insert("chats_log", &[incoming(1, 10, "v1")])
+            .await
+            .unwrap();
+        let edit = |message: &str, media: &str, media_id: i64| Edited {
+            date_time: 1_700_000_100,
+            chat_id: 1,
+            message_id: 10,
+            message: message.to_string(),
+            media: media.to_string(),
+            media_id,
+        };
+        s.insert(
+            "edited_log",
+            &[edit("v2", "[photo]", 5), edit("v3", "[video]", 6)],
+        )
+        .await
+        .unwrap();
+        let latest = s.latest_content(1, 10).await.unwrap().unwrap();
+        assert_eq!(
+            (
+                latest.message.as_str(),
+                latest.media.as_str(),
+                latest.media_id
+            ),
+            ("v3", "[video]", 6)
+        );
+        assert_eq!(s.incoming(1, 10).await.unwrap().unwrap().message, "v1");
+    }
+
+    #[tokio::test]
+    async fn outgoing_reads_our_own_messages() {
+        let s = MemoryStorage::default();
+        assert!(s.outgoing(1, 13).await.unwrap().is_none());
+        let mine = Outgoing {
+            date_time: 200,
+            id: 1,
+            title: "chat".to_string(),
+            message_id: 13,
+            message: "sent".to_string(),
+            client_id: 42,
+        };
+        s.insert("telegram_messages_new", &[mine]).await.unwrap();
+
+        let m = s.outgoing(1, 13).await.unwrap().unwrap();
+        assert_eq!(
+            (m.message.as_str(), m.chat_title.as_str()),
+            ("sent", "chat")
+        );
+        assert_eq!((m.user_id, m.date_time), (42, 200));
+        assert!(m.first_name.is_empty() && m.second_name.is_empty());
+        assert!(s.incoming(1, 13).await.unwrap().is_none());
+        assert_eq!(
+            s.latest_content(1, 13).await.unwrap().unwrap().message,
+            "sent"
+        );
+    }
+
+    #[tokio::test]
+    async fn message_exists_checks_chat_and_id() {
+        let s = MemoryStorage::default();
+        s.insert("chats_log", &[incoming(1, 10, "hello")])
+            .await
+            .unwrap();
+        assert!(s.message_exists(1, 10).await.unwrap());
+        assert!(!s.message_exists(1, 11).await.unwrap());
+        assert!(!s.message_exists(2, 10).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn search_filters_and_pages_newest_first() {
+        let s = MemoryStorage::default();
+        let mut old = incoming(1, 10, "Hello world");
+        old.date_time = 100;
+        let mut new = incoming(2, 11, "hello again");
+        new.date_time = 300;
+        s.insert("chats_log", &[old, new, incoming(1, 12, "bye")])
+            .await
+            .unwrap();
+        let mine = Outgoing {
+            date_time: 200,
+            id: 1,
+            title: "chat".to_string(),
+            message_id: 13,
+            message: "hello from me".to_string(),
+            client_id: 42,
+        };
+        s.insert("telegram_messages_new", &[mine]).await.unwrap();
+
+        let query = SearchQuery {
+            terms: vec!["HELLO".to_string()],
+            ..Default::default()
+        };
+        let ids = |hits: Vec<crate::storage::SearchHit>| {
+            hits.iter()
+                .map(|h| (h.message_id, h.source.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ids(s.search(&query, 10, 0).await.unwrap()),
+            [
+                (11, "incoming".to_string()),
+                (13, "outgoing".to_string()),
+                (10, "incoming".to_string())
+            ]
+        );
+        assert_eq!(
+            ids(s.search(&query, 1, 1).await.unwrap()),
+            [(13, "outgoing".to_string())]
+        );
+
+        let mine_in_chat_1 = SearchQuery {
+            user_id: Some(42),
+            chat_id: Some(1),
+            ..query
+        };
+        assert_eq!(
+            ids(s.search(&mine_in_chat_1, 10, 0).await.unwrap()),
+            [(13, "outgoing".to_string())]
+        );
+        let pattern = SearchQuery {
+            regex: Some(regex::Regex::new("^H.*d$").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(s.search(&pattern, 10, 0).await.unwrap()),
+            [(10, "incoming".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn backfill_cursor_is_latest_per_chat() {
+        let s = MemoryStorage::default();
+        assert!(s.backfill_cursor(-1001).await.unwrap().is_none());
+        let cursor = |chat_id: i64, oldest_id: i32, done: bool| BackfillCursor {
+            chat_id,
+            oldest_id,
+            done,
+        };
+        s.insert(
+            "backfill_cursors",
+            &[
+                cursor(-1001, 500, false),
+                cursor(7, 90, false),
+                cursor(-1001, 1, true),
+            ],
+        )
+        .await
+        .unwrap();
+        let c = s.backfill_cursor(-1001).await.unwrap().unwrap();
+        assert_eq!((c.oldest_id, c.done), (1, true));
+        let c = s.backfill_cursor(7).await.unwrap().unwrap();
+        assert_eq!((c.oldest_id, c.done), (90, false));
+    }
+
+    #[tokio::test]
+    async fn last_admin_event_id_is_per_chat_max() {
+        let s = MemoryStorage::default();
+        assert_eq!(s.last_admin_event_id(5).await.unwrap(), 0);
+        let rows = [
+            AdminAction {
+                chat_id: 5,
+                event_id: 30,
+            },
+            AdminAction {
+                chat_id: 5,
+                event_id: 40,
+            },
+            AdminAction {
+                chat_id: 6,
+                event_id: 99,
+            },
+        ];
+        s.insert("admin_actions2", &rows).await.unwrap();
+        assert_eq!(s.last_admin_event_id(5).await.unwrap(), 40);
+        assert_eq!(s.last_admin_event_id(6).await.unwrap(), 99);
+    }
+
+    #[tokio::test]
+    async fn session_round_trip() {
+        let s = MemoryStorage::default();
+        let empty = s.load_session().await.unwrap();
+        assert!(empty.home_dc.is_none() && empty.update_state.is_none());
+
+        s.insert(
+            "session_dc_home",
+            &[DcHomeRow { dc_id: 2 }, DcHomeRow { dc_id: 4 }],
+        )
+        .await
+        .unwrap();
+        let option = |dc_id: i32, ipv4: &str| DcOptionRow {
+            dc_id,
+            ipv4: ipv4.to_string(),
+            ipv6: String::new(),
+            auth_key: None,
+        };
+        s.insert(
+            "session_dc_option",
+            &[option(2, "a"), option(4, "b"), option(2, "c")],
+        )
+        .await
+        .unwrap();
+        let state = UpdateStateRow {
+            pts: 1,
+            qts: 2,
+            date: 3,
+            seq: 4,
+        };
+        s.insert("session_update_state", &[state]).await.unwrap();
+        let channel = |peer_id: i64, pts: i32| ChannelStateRow { peer_id, pts };
+        s.insert(
+            "session_channel_state",
+            &[channel(100, 5), channel(100, 6), channel(200, 1)],
+        )
+        .await
+        .unwrap();
+        let me = PeerRow {
+            peer_id: 42,
+            hash: Some(9),
+            subtype: Some(1),
+        };
+        s.insert("peer_cache", &[me]).await.unwrap();
+
+        let session = s.load_session().await.unwrap();
+        assert_eq!(session.home_dc, Some(4));
+        let options: Vec<_> = session
+            .dc_options
+            .iter()
+            .map(|o| (o.dc_id, o.ipv4.as_str()))
+            .collect();
+        assert_eq!(options, [(2, "c"), (4, "b")]);
+        let state = session.update_state.unwrap();
+        assert_eq!((state.pts, state.qts, state.date, state.seq), (1, 2, 3, 4));
+        let channels: Vec<_> = session
+            .channels
+            .iter()
+            .map(|c| (c.peer_id, c.pts))
+            .collect();
+        assert_eq!(channels, [(100, 6), (200, 1)]);
+        assert_eq!(s.peer(None).await.unwrap().map(|p| p.peer_id), Some(42));
+        assert_eq!(
+            s.peer(Some(42)).await.unwrap().and_then(|p| p.hash),
+            Some(9)
+        );
+
+        s.clear_channel_states().await.unwrap();
+        assert!(s.load_session().await.unwrap().channels.is_empty());
+    }
+}
@@ -0,0 +1,406 @@
+mod clickhouse;
+mod memory;
+mod sqlite;
+
+pub use self::clickhouse::ClickHouseStorage;
+pub use memory::MemoryStorage;
+pub use sqlite::SqliteStorage;
+
+use std::fmt;
+use std::future::Future;
+use std::sync::LazyLock;
+
+use ::clickhouse::Row;
+use serde::{Deserialize, Serialize};
+
+/// Persistence used by handlers, schedulers and the session.
+///
+/// `STORAGE` selects the backend: `clickhouse` (default), `sqlite` (file at
+/// `SQLITE_PATH`) for small single-user deployments, or `memory` for tests.
+static STORAGE: LazyLock<Backend> = LazyLock::new(|| {
+    // Tests never reach a real database.
+    let default = if cfg!(test) { "memory" } else { "clickhouse" };
+    let kind = std::env::var("STORAGE").unwrap_or_else(|_| default.to_string());
+    match kind.as_str() {
+        "clickhouse" => Backend::ClickHouse(ClickHouseStorage),
+        "sqlite" => {
+            let path = std::env::var("SQLITE_PATH")
+                .unwrap_or_else(|_| "telegram_user_bot.sqlite3".to_string());
+            Backend::Sqlite(SqliteStorage::open(&path).expect("cannot open SQLite storage"))
+        }
+        "memory" => Backend::Memory(MemoryStorage::default()),
+        other => panic!("STORAGE invalid: {other}"),
+    }
+});
+
+pub fn storage() -> &'static Backend {
+    &STORAGE
+}
+
+pub trait Storage: Send + Sync {
+    /// Append rows to `table`.
+    fn insert<T>(&self, table: &str, rows: &[T]) -> impl Future<Output = Result<()>> + Send
+    where
+        T: Serialize + Send + Sync,
+        for<'a> T: Row<Value<'a> = T>;
+
+    /// Latest known text and media of a message: last edit first, then
+    /// chats_log, then telegram_messages_new.
+    fn latest_content(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+    ) -> impl Future<Output = Result<Option<MessageContent>>> + Send;
+
+    /// Latest chats_log row for a message.
+    fn incoming(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+    ) -> impl Future<Output = Result<Option<StoredMessage>>> + Send;
+
+    /// Latest chat title seen in chats_log.
+    fn chat_title(&self, chat_id: i64) -> impl Future<Output = Result<Option<String>>> + Send;
+
+    /// Latest telegram_messages_new row for one of our own messages. The chat
+    /// title is in `chat_title`, our user id in `user_id`, names are empty.
+    fn outgoing(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+    ) -> impl Future<Output = Result<Option<StoredMessage>>> + Send;
+
+    /// Latest non-empty title and usernames we stored for a chat in telegram_messages_new.
+    fn outgoing_chat(
+        &self,
+        chat_id: i64,
+    ) -> impl Future<Output = Result<Option<(String, Vec<String>)>>> + Send;
+
+    /// Whether the message is stored in chats_log or telegram_messages_new.
+    fn message_exists(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+    ) -> impl Future<Output = Result<bool>> + Send;
+
+    /// Up to `limit` latest incoming and `limit` latest outgoing messages, oldest first.
+    fn recent_messages(
+        &self,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<RecentMessage>>> + Send;
+
+    /// Latest edit of every message edited at or after `since`.
+    fn recent_edits(&self, since: u32) -> impl Future<Output = Result<Vec<RecentEdit>>> + Send;
+
+    /// Messages matching `query` across chats_log, telegram_messages_new and
+    /// edited_log, newest first, skipping `offset` and returning at most `limit`.
+    fn search(
+        &self,
+        query: &SearchQuery,
+        limit: usize,
+        offset: usize,
+    ) -> impl Future<Output = Result<Vec<SearchHit>>> + Send;
+
+    /// Progress of the history backfill of a chat (Bot API id), if it was started.
+    fn backfill_cursor(
+        &self,
+        chat_id: i64,
+    ) -> impl Future<Output = Result<Option<BackfillCursor>>> + Send;
+
+    /// Highest admin log event id stored for a channel, 0 if none.
+    fn last_admin_event_id(&self, chat_id: u64) -> impl Future<Output = Result<u64>> + Send;
+
+    /// Everything the session needs at startup.
+    fn load_session(&self) -> impl Future<Output = Result<SessionSnapshot>> + Send;
+
+    /// A cached peer by bot API dialog id, or our own user when `peer_id` is `None`.
+    fn peer(&self, peer_id: Option<i64>) -> impl Future<Output = Result<Option<PeerRow>>> + Send;
+
+    /// Drop all per-channel update states before they are rewritten.
+    fn clear_channel_states(&self) -> impl Future<Output = Result<()>> + Send;
+}
+
+pub enum Backend {
+    ClickHouse(ClickHouseStorage),
+    Sqlite(SqliteStorage),
+    Memory(MemoryStorage),
+}
+
+impl Backend {
+    pub fn is_clickhouse(&self) -> bool {
+        matches!(self, Backend::ClickHouse(_))
+    }
+}
+
+macro_rules! dispatch {
+    ($self:ident, $s:ident => $call:expr) => {
+        match $self {
+            Backend::ClickHouse($s) => $call.await,
+            Backend::Sqlite($s) => $call.await,
+            Backend::Memory($s) => $call.await,
+        }
+    };
+}
+
+impl Storage for Backend {
+    async fn insert<T>(&self, table: &str, rows: &[T]) -> Result<()>
+    where
+        T: Serialize + Send + Sync,
+        for<'a> T: Row<Value<'a> = T>,
+    {
+        dispatch!(self, s => s.insert(table, rows))
+    }
+
+    async fn latest_content(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+    ) -> Result<Option<MessageContent>> {
+        dispatch!(self, s => s.latest_content(chat_id, message_id))
+    }
+
+    async fn incoming(&self, chat_id: i64, message_id: i64) -> Result<Option<StoredMessage>> {
+        dispatch!(self, s => s.incoming(chat_id, message_id))
+    }
+
+    async fn chat_title(&self, chat_id: i64) -> Result<Option<String>> {
+        dispatch!(self, s => s.chat_title(chat_id))
+    }
+
+    async fn outgoing(&self, chat_id: i64, message_id: i64) -> Result<Option<StoredMessage>> {
+        dispatch!(self, s => s.outgoing(chat_id, message_id))
+    }
+
+    async fn outgoing_chat(&self, chat_id: i64) -> Result<Option<(String, Vec<String>)>> {
+        dispatch!(self, s => s.outgoing_chat(chat_id))
+    }
+
+    async fn message_exists(&self, chat_id: i64, message_id: i64) -> Result<bool> {
+        dispatch!(self, s => s.message_exists(chat_id, message_id))
+    }
+
+    async fn recent_messages(&self, limit: usize) -> Result<Vec<RecentMessage>> {
+        dispatch!(self, s => s.recent_messages(limit))
+    }
+
+    async fn recent_edits(&self, since: u32) -> Result<Vec<RecentEdit>> {
+        dispatch!(self, s => s.recent_edits(since))
+    }
+
+    async fn search(
+        &self,
+        query: &SearchQuery,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<SearchHit>> {
+        dispatch!(self, s => s.search(query, limit, offset))
+    }
+
+    async fn backfill_cursor(&self, chat_id: i64) -> Result<Option<BackfillCursor>> {
+        dispatch!(self, s => s.backfill_cursor(chat_id))
+    }
+
+    async fn last_admin_event_id(&self, chat_id: u64) -> Result<u64> {
+        dispatch!(self, s => s.last_admin_event_id(chat_id))
+    }
+
+    async fn load_session(&self) -> Result<SessionSnapshot> {
+        dispatch!(self, s => s.load_session())
+    }
+
+    async fn peer(&self, peer_id: Option<i64>) -> Result<Option<PeerRow>> {
+        dispatch!(self, s => s.peer(peer_id))
+    }
+
+    async fn clear_channel_states(&self) -> Result<()> {
+        dispatch!(self, s => s.clear_channel_states())
+    }
+}
+
+/// `peer_cache` stores bot API dialog ids; a channel's is `CHANNEL_ID_OFFSET - bare id`.
+pub const CHANNEL_ID_OFFSET: i64 = -1_000_000_000_000;
+
+// ── Errors ──────────────────────────────────────────────────────────
+
+pub type Result<T> = std::result::Result<T, StorageError>;
+
+#[derive(Debug)]
+pub enum StorageError {
+    ClickHouse(::clickhouse::error::Error),
+    Sqlite(rusqlite::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::ClickHouse(e) => write!(f, "clickhouse: {e}"),
+            StorageError::Sqlite(e) => write!(f, "sqlite: {e}"),
+            StorageError::Json(e) => write!(f, "json: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<::clickhouse::error::Error> for StorageError {
+    fn from(e: ::clickhouse::error::Error) -> Self {
+        StorageError::ClickHouse(e)
+    }
+}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> Self {
+        StorageError::Sqlite(e)
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(e: serde_json::Error) -> Self {
+        StorageError::Json(e)
+    }
+}
+
+// ── Query results ───────────────────────────────────────────────────
+
+#[derive(Row, Deserialize, Default)]
+pub struct MessageContent {
+    pub message: String,
+    pub media: String,
+    pub media_id: i64,
+}
+
+#[derive(Row, Deserialize)]
+pub struct StoredMessage {
+    pub message: String,
+    pub chat_title: String,
+    pub first_name: String,
+    pub second_name: String,
+    pub user_id: u64,
+    pub date_time: u32,
+    pub media: String,
+    pub media_id: i64,
+}
+
+#[derive(Row, Deserialize)]
+pub struct RecentMessage {
+    pub date_time: u32,
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub message: String,
+    pub chat_title: String,
+    pub first_name: String,
+    pub second_name: String,
+    pub user_id: u64,
+    pub media: String,
+    pub media_id: i64,
+    pub outgoing: bool,
+}
+
+#[derive(Row, Deserialize)]
+pub struct RecentEdit {
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub message: String,
+    pub media: String,
+    pub media_id: i64,
+}
+
+/// Filters for `Storage::search`; all that are set must match.
+#[derive(Default)]
+pub struct SearchQuery {
+    /// Words or phrases that must all occur, ignoring case.
+    pub terms: Vec<String>,
+    /// ClickHouse evaluates it with RE2; the search command rejects syntax that
+    /// only `regex` knows.
+    pub regex: Option<regex::Regex>,
+    pub chat_id: Option<i64>,
+    /// Sender; for our own messages, our user id.
+    pub user_id: Option<u64>,
+    pub since: Option<u32>,
+    pub until: Option<u32>,
+}
+
+impl SearchQuery {
+    /// Text conditions, for backends that cannot evaluate them in SQL.
+    pub fn matches_text(&self, text: &str) -> bool {
+        let lower = text.to_lowercase();
+        self.terms.iter().all(|t| lower.contains(&t.to_lowercase()))
+            && self.regex.as_ref().is_none_or(|re| re.is_match(text))
+    }
+
+    /// Chat, sender and date conditions, for backends that filter in Rust.
+    pub fn matches_row(&self, chat_id: i64, user_id: u64, date_time: u32) -> bool {
+        self.chat_id.is_none_or(|c| c == chat_id)
+            && self.user_id.is_none_or(|u| u == user_id)
+            && self.since.is_none_or(|s| date_time >= s)
+            && self.until.is_none_or(|u| date_time <= u)
+    }
+}
+
+#[derive(Row, Deserialize)]
+pub struct SearchHit {
+    pub date_time: u32,
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub chat_title: String,
+    pub sender: String,
+    pub user_id: u64,
+    pub message: String,
+    /// "incoming", "outgoing" or "edited".
+    pub source: String,
+}
+
+/// History is fetched newest first; everything from `oldest_id` up was seen.
+#[derive(Row, Serialize, Deserialize)]
+pub struct BackfillCursor {
+    /// Bot API id.
+    pub chat_id: i64,
+    pub oldest_id: i32,
+    /// The start of the chat was reached.
+    pub done: bool,
+}
+
+// ── Session rows ────────────────────────────────────────────────────
+
+#[derive(Row, Serialize, Deserialize)]
+pub struct PeerRow {
+    pub peer_id: i64,
+    pub hash: Option<i64>,
+    pub subtype: Option<u8>,
+}
+
+#[derive(Row, Serialize, Deserialize)]
+pub struct DcHomeRow {
+    pub dc_id: i32,
+}
+
+#[derive(Row, Serialize, Deserialize)]
+pub struct DcOptionRow {
+    pub dc_id: i32,
+    pub ipv4: String,
+    pub ipv6: String,
+    pub auth_key: Option<String>,
+}
+
+#[derive(Row, Serialize, Deserialize)]
+pub struct UpdateStateRow {
+    pub pts: i32,
+    pub qts: i32,
+    pub date: i32,
+    pub seq: i32,
+}
+
+#[derive(Row, Serialize, Deserialize)]
+pub struct ChannelStateRow {
+    pub peer_id: i64,
+    pub pts: i32,
+}
+
+#[derive(Default)]
+pub struct SessionSnapshot {
+    pub home_dc: Option<i32>,
+    pub dc_options: Vec<DcOptionRow>,
+    pub update_state: Option<UpdateStateRow>,
+    pub channels: Vec<ChannelStateRow>,
+}
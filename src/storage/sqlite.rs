@@ -0,0 +1,616 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use ::clickhouse::Row;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{Connection, OptionalExtension, params, params_from_iter};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::{
+    BackfillCursor, ChannelStateRow, DcOptionRow, MessageContent, PeerRow, RecentEdit,
+    RecentMessage, Result, SearchHit, SearchQuery, SessionSnapshot, Storage, StoredMessage,
+    UpdateStateRow,
+};
+
+/// Single-file backend for small single-user deployments.
+///
+/// Tables are created from the first row written to them and gain columns as
+/// rows grow new fields, so no migrations are needed. Arrays are stored as JSON
+/// text. Tables that ClickHouse deduplicates (`FINAL`) are read by taking the
+/// most recently inserted row per key.
+///
+/// rusqlite is blocking, so every call runs on tokio's blocking pool.
+pub struct SqliteStorage {
+    db: Arc<Db>,
+}
+
+struct Db {
+    conn: Mutex<Connection>,
+    /// Known columns per table, filled lazily from `PRAGMA table_info`.
+    columns: Mutex<HashMap<String, HashSet<String>>>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &str) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")?;
+        register_functions(&conn)?;
+        Ok(Self {
+            db: Arc::new(Db {
+                conn: Mutex::new(conn),
+                columns: Mutex::new(HashMap::new()),
+            }),
+        })
+    }
+
+    async fn run<R, F>(&self, f: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&Db) -> Result<R> + Send + 'static,
+    {
+        let db = Arc::clone(&self.db);
+        match tokio::task::spawn_blocking(move || f(&db)).await {
+            Ok(result) => result,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+impl Db {
+    fn insert(&self, table: &str, records: Vec<Map<String, Value>>) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for map in records {
+            self.ensure_columns(&tx, table, &map)?;
+            let cols: Vec<String> = map.keys().map(|k| format!("\"{k}\"")).collect();
+            let marks = vec!["?"; cols.len()].join(", ");
+            let sql = format!(
+                "INSERT INTO \"{table}\" ({}) VALUES ({marks})",
+                cols.join(", ")
+            );
+            tx.execute(
+                &sql,
+                params_from_iter(map.into_iter().map(|(_, v)| to_sql(v))),
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn ensure_columns(
+        &self,
+        conn: &Connection,
+        table: &str,
+        row: &Map<String, Value>,
+    ) -> Result<()> {
+        let mut known = self.columns.lock().unwrap();
+        if !known.contains_key(table) {
+            let mut stmt = conn.prepare(&format!("PRAGMA table_info(\"{table}\")"))?;
+            let existing = stmt
+                .query_map([], |r| r.get::<_, String>(1))?
+                .collect::<rusqlite::Result<HashSet<String>>>()?;
+            known.insert(table.to_string(), existing);
+        }
+        let cols = known.get_mut(table).unwrap();
+
+        if cols.is_empty() {
+            let defs: Vec<String> = row.keys().map(|k| format!("\"{k}\"")).collect();
+            conn.execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS \"{table}\" ({})",
+                defs.join(", ")
+            ))?;
+            cols.extend(row.keys().cloned());
+            return Ok(());
+        }
+
+        for key in row.keys() {
+            if !cols.contains(key) {
+                conn.execute_batch(&format!("ALTER TABLE \"{table}\" ADD COLUMN \"{key}\""))?;
+                cols.insert(key.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn query_opt<T>(
+        &self,
+        sql: &str,
+        params: impl rusqlite::Params,
+        f: impl FnOnce(&rusqlite::Row<'_>) -> rusqlite::Result<T>,
+    ) -> Result<Option<T>> {
+        let conn = self.conn.lock().unwrap();
+        match conn.query_row(sql, params, f).optional() {
+            Ok(v) => Ok(v),
+            Err(e) if missing_schema(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn table_exists(&self, table: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn
+            .query_row(
+                "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                params![table],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some())
+    }
+
+    fn query_all<T>(
+        &self,
+        sql: &str,
+        params: impl rusqlite::Params,
+        f: impl FnMut(&rusqlite::Row<'_>) -> rusqlite::Result<T>,
+    ) -> Result<Vec<T>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = match conn.prepare(sql) {
+            Ok(stmt) => stmt,
+            Err(e) if missing_schema(&e) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let rows = stmt
+            .query_map(params, f)?
+            .collect::<rusqlite::Result<Vec<T>>>()?;
+        Ok(rows)
+    }
+}
+
+/// Text matching for `search`, with the same semantics as the other backends:
+/// `icontains(text, term)` ignores case beyond ASCII, unlike SQLite's `LIKE`,
+/// and `text REGEXP pattern` uses the `regex` crate.
+fn register_functions(conn: &Connection) -> rusqlite::Result<()> {
+    let flags = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC;
+    conn.create_scalar_function("icontains", 2, flags, |ctx| {
+        let text = ctx.get::<Option<String>>(0)?.unwrap_or_default();
+        let term: String = ctx.get(1)?;
+        Ok(text.to_lowercase().contains(&term.to_lowercase()))
+    })?;
+    conn.create_scalar_function("regexp", 2, flags, |ctx| {
+        // Compiled once per statement.
+        let re = ctx.get_or_create_aux(0, |pattern| -> std::result::Result<_, BoxError> {
+            Ok(regex::Regex::new(pattern.as_str()?)?)
+        })?;
+        let text = ctx.get::<Option<String>>(1)?.unwrap_or_default();
+        Ok(re.is_match(&text))
+    })
+}
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Tables and columns only exist once a row with them was written.
+fn missing_schema(e: &rusqlite::Error) -> bool {
+    let msg = e.to_string();
+    msg.contains("no such table") || msg.contains("no such column")
+}
+
+fn to_sql(value: Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(b as i64),
+        Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => SqlValue::Integer(i),
+            (None, Some(u)) => SqlValue::Integer(u as i64),
+            _ => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => SqlValue::Text(s),
+        other => SqlValue::Text(other.to_string()),
+    }
+}
+
+impl Storage for SqliteStorage {
+    async fn insert<T>(&self, table: &str, rows: &[T]) -> Result<()>
+    where
+        T: Serialize + Send + Sync,
+        for<'a> T: Row<Value<'a> = T>,
+    {
+        let mut records = Vec::with_capacity(rows.len());
+        for row in rows {
+            if let Value::Object(map) = serde_json::to_value(row)? {
+                records.push(map);
+            }
+        }
+        let table = table.to_string();
+        self.run(move |db| db.insert(&table, records)).await
+    }
+
+    async fn latest_content(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+    ) -> Result<Option<MessageContent>> {
+        self.run(move |db| {
+            let content = |r: &rusqlite::Row<'_>| {
+                Ok(MessageContent {
+                    message: r.get(0)?,
+                    media: r.get(1)?,
+                    media_id: r.get(2)?,
+                })
+            };
+            let edited = db.query_opt(
+                "SELECT message, COALESCE(media, ''), COALESCE(media_id, 0) FROM edited_log WHERE chat_id = ?1 AND message_id = ?2 ORDER BY date_time DESC, rowid DESC LIMIT 1",
+                params![chat_id, message_id],
+                content,
+            )?;
+            if edited.is_some() {
+                return Ok(edited);
+            }
+            let incoming = db.query_opt(
+                "SELECT message, COALESCE(media, ''), COALESCE(media_id, 0) FROM chats_log WHERE chat_id = ?1 AND message_id = ?2 ORDER BY date_time DESC, rowid DESC LIMIT 1",
+                params![chat_id, message_id],
+                content,
+            )?;
+            if incoming.is_some() {
+                return Ok(incoming);
+            }
+            db.query_opt(
+                "SELECT message, COALESCE(media, ''), COALESCE(media_id, 0) FROM telegram_messages_new WHERE id = ?1 AND message_id = ?2 ORDER BY date_time DESC, rowid DESC LIMIT 1",
+                params![chat_id, message_id],
+                content,
+            )
+        })
+        .await
+    }
+
+    async fn incoming(&self, chat_id: i64, message_id: i64) -> Result<Option<StoredMessage>> {
+        self.run(move |db| {
+            db.query_opt(
+                "SELECT message, chat_title, first_name, second_name, user_id, date_time, COALESCE(media, ''), COALESCE(media_id, 0) FROM chats_log WHERE chat_id = ?1 AND message_id = ?2 ORDER BY date_time DESC, rowid DESC LIMIT 1",
+                params![chat_id, message_id],
+                |r| {
+                    Ok(StoredMessage {
+                        message: r.get(0)?,
+                        chat_title: r.get(1)?,
+                        first_name: r.get(2)?,
+                        second_name: r.get(3)?,
+                        user_id: r.get(4)?,
+                        date_time: r.get(5)?,
+                        media: r.get(6)?,
+                        media_id: r.get(7)?,
+                    })
+                },
+            )
+        })
+        .await
+    }
+
+    async fn chat_title(&self, chat_id: i64) -> Result<Option<String>> {
+        self.run(move |db| {
+            db.query_opt(
+                "SELECT chat_title FROM chats_log WHERE chat_id = ?1 ORDER BY date_time DESC, rowid DESC LIMIT 1",
+                params![chat_id],
+                |r| r.get(0),
+            )
+        })
+        .await
+    }
+
+    async fn outgoing(&self, chat_id: i64, message_id: i64) -> Result<Option<StoredMessage>> {
+        self.run(move |db| {
+            db.query_opt(
+                "SELECT message, title, client_id, date_time, COALESCE(media, ''), COALESCE(media_id, 0) FROM telegram_messages_new WHERE id = ?1 AND message_id = ?2 ORDER BY date_time DESC, rowid DESC LIMIT 1",
+                params![chat_id, message_id],
+                |r| {
+                    Ok(StoredMessage {
+                        message: r.get(0)?,
+                        chat_title: r.get(1)?,
+                        first_name: String::new(),
+                        second_name: String::new(),
+                        user_id: r.get(2)?,
+                        date_time: r.get(3)?,
+                        media: r.get(4)?,
+                        media_id: r.get(5)?,
+                    })
+                },
+            )
+        })
+        .await
+    }
+
+    async fn outgoing_chat(&self, chat_id: i64) -> Result<Option<(String, Vec<String>)>> {
+        self.run(move |db| {
+            let row: Option<(String, String)> = db.query_opt(
+                "SELECT title, usernames FROM telegram_messages_new WHERE id = ?1 AND title != '' ORDER BY date_time DESC, rowid DESC LIMIT 1",
+                params![chat_id],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )?;
+            Ok(row.map(|(title, usernames)| {
+                (title, serde_json::from_str(&usernames).unwrap_or_default())
+            }))
+        })
+        .await
+    }
+
+    async fn message_exists(&self, chat_id: i64, message_id: i64) -> Result<bool> {
+        self.run(move |db| {
+            let incoming = db.query_opt(
+                "SELECT 1 FROM chats_log WHERE chat_id = ?1 AND message_id = ?2 LIMIT 1",
+                params![chat_id, message_id],
+                |r| r.get::<_, i64>(0),
+            )?;
+            if incoming.is_some() {
+                return Ok(true);
+            }
+            let outgoing = db.query_opt(
+                "SELECT 1 FROM telegram_messages_new WHERE id = ?1 AND message_id = ?2 LIMIT 1",
+                params![chat_id, message_id],
+                |r| r.get::<_, i64>(0),
+            )?;
+            Ok(outgoing.is_some())
+        })
+        .await
+    }
+
+    async fn recent_messages(&self, limit: usize) -> Result<Vec<RecentMessage>> {
+        self.run(move |db| {
+            let map = |outgoing: bool| {
+                move |r: &rusqlite::Row<'_>| -> rusqlite::Result<RecentMessage> {
+                    Ok(RecentMessage {
+                        date_time: r.get(0)?,
+                        chat_id: r.get(1)?,
+                        message_id: r.get(2)?,
+                        message: r.get(3)?,
+                        chat_title: r.get(4)?,
+                        first_name: r.get(5)?,
+                        second_name: r.get(6)?,
+                        user_id: r.get(7)?,
+                        media: r.get(8)?,
+                        media_id: r.get(9)?,
+                        outgoing,
+                    })
+                }
+            };
+            let limit = limit as i64;
+            let mut recent = db.query_all(
+                "SELECT date_time, chat_id, message_id, message, chat_title, first_name, second_name, \
+                 user_id, COALESCE(media, ''), COALESCE(media_id, 0) FROM chats_log ORDER BY rowid DESC LIMIT ?1",
+                params![limit],
+                map(false),
+            )?;
+            recent.extend(db.query_all(
+                "SELECT date_time, id, message_id, message, title, '', '', client_id, \
+                 COALESCE(media, ''), COALESCE(media_id, 0) \
+                 FROM telegram_messages_new ORDER BY rowid DESC LIMIT ?1",
+                params![limit],
+                map(true),
+            )?);
+            recent.sort_by_key(|m| m.date_time);
+            Ok(recent)
+        })
+        .await
+    }
+
+    async fn recent_edits(&self, since: u32) -> Result<Vec<RecentEdit>> {
+        self.run(move |db| {
+            db.query_all(
+                "SELECT chat_id, message_id, message, COALESCE(media, ''), COALESCE(media_id, 0) \
+                 FROM edited_log WHERE rowid IN \
+                 (SELECT max(rowid) FROM edited_log WHERE date_time >= ?1 GROUP BY chat_id, message_id)",
+                params![since],
+                |r| {
+                    Ok(RecentEdit {
+                        chat_id: r.get(0)?,
+                        message_id: r.get(1)?,
+                        message: r.get(2)?,
+                        media: r.get(3)?,
+                        media_id: r.get(4)?,
+                    })
+                },
+            )
+        })
+        .await
+    }
+
+    async fn search(
+        &self,
+        query: &SearchQuery,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<SearchHit>> {
+        let terms = query.terms.clone();
+        let regex = query.regex.as_ref().map(|re| re.as_str().to_string());
+        // Numeric filters are inlined, text ones bound in the order they appear.
+        let numeric = |chat: &str, user: &str| {
+            let mut conds = Vec::new();
+            if let Some(chat_id) = query.chat_id {
+                conds.push(format!("{chat} = {chat_id}"));
+            }
+            if let Some(user_id) = query.user_id {
+                conds.push(format!("{user} = {user_id}"));
+            }
+            if let Some(since) = query.since {
+                conds.push(format!("date_time >= {since}"));
+            }
+            if let Some(until) = query.until {
+                conds.push(format!("date_time <= {until}"));
+            }
+            conds
+        };
+        let sources = [
+            (
+                "chats_log",
+                "SELECT date_time, chat_id, message_id, chat_title, \
+                 first_name || ' ' || second_name AS sender, user_id, message, 'incoming' AS source \
+                 FROM chats_log",
+                numeric("chat_id", "user_id"),
+            ),
+            (
+                "telegram_messages_new",
+                "SELECT date_time, id, message_id, title, '', client_id, message, 'outgoing' \
+                 FROM telegram_messages_new",
+                numeric("id", "client_id"),
+            ),
+            (
+                "edited_log",
+                "SELECT date_time, chat_id, message_id, '', '', user_id, message, 'edited' \
+                 FROM edited_log",
+                numeric("chat_id", "user_id"),
+            ),
+        ];
+        self.run(move |db| {
+            let mut selects = Vec::new();
+            let mut binds = Vec::new();
+            for (table, select, mut conds) in sources {
+                // A table is only created by its first row.
+                if !db.table_exists(table)? {
+                    continue;
+                }
+                for term in &terms {
+                    conds.push("icontains(message, ?)".to_string());
+                    binds.push(term.clone());
+                }
+                if let Some(re) = &regex {
+                    conds.push("message REGEXP ?".to_string());
+                    binds.push(re.clone());
+                }
+                if conds.is_empty() {
+                    selects.push(select.to_string());
+                } else {
+                    selects.push(format!("{select} WHERE {}", conds.join(" AND ")));
+                }
+            }
+            if selects.is_empty() {
+                return Ok(Vec::new());
+            }
+            let sql = format!(
+                "SELECT * FROM ({}) ORDER BY date_time DESC LIMIT {limit} OFFSET {offset}",
+                selects.join(" UNION ALL ")
+            );
+            db.query_all(&sql, params_from_iter(binds), |r| {
+                Ok(SearchHit {
+                    date_time: r.get(0)?,
+                    chat_id: r.get(1)?,
+                    message_id: r.get(2)?,
+                    chat_title: r.get(3)?,
+                    sender: r.get::<_, String>(4)?.trim().to_string(),
+                    user_id: r.get(5)?,
+                    message: r.get(6)?,
+                    source: r.get(7)?,
+                })
+            })
+        })
+        .await
+    }
+
+    async fn backfill_cursor(&self, chat_id: i64) -> Result<Option<BackfillCursor>> {
+        self.run(move |db| {
+            db.query_opt(
+                "SELECT chat_id, oldest_id, done FROM backfill_cursors WHERE chat_id = ?1 ORDER BY rowid DESC LIMIT 1",
+                params![chat_id],
+                |r| {
+                    Ok(BackfillCursor {
+                        chat_id: r.get(0)?,
+                        oldest_id: r.get(1)?,
+                        done: r.get(2)?,
+                    })
+                },
+            )
+        })
+        .await
+    }
+
+    async fn last_admin_event_id(&self, chat_id: u64) -> Result<u64> {
+        self.run(move |db| {
+            let max: Option<Option<i64>> = db.query_opt(
+                "SELECT max(event_id) FROM admin_actions2 WHERE chat_id = ?1",
+                params![chat_id as i64],
+                |r| r.get(0),
+            )?;
+            Ok(max.flatten().unwrap_or(0) as u64)
+        })
+        .await
+    }
+
+    async fn load_session(&self) -> Result<SessionSnapshot> {
+        self.run(move |db| {
+            let home_dc = db.query_opt(
+                "SELECT dc_id FROM session_dc_home ORDER BY rowid DESC LIMIT 1",
+                [],
+                |r| r.get(0),
+            )?;
+            let dc_options = db.query_all(
+                "SELECT dc_id, ipv4, ipv6, auth_key FROM session_dc_option \
+                 WHERE rowid IN (SELECT max(rowid) FROM session_dc_option GROUP BY dc_id)",
+                [],
+                |r| {
+                    Ok(DcOptionRow {
+                        dc_id: r.get(0)?,
+                        ipv4: r.get(1)?,
+                        ipv6: r.get(2)?,
+                        auth_key: r.get(3)?,
+                    })
+                },
+            )?;
+            let update_state = db.query_opt(
+                "SELECT pts, qts, date, seq FROM session_update_state ORDER BY rowid DESC LIMIT 1",
+                [],
+                |r| {
+                    Ok(UpdateStateRow {
+                        pts: r.get(0)?,
+                        qts: r.get(1)?,
+                        date: r.get(2)?,
+                        seq: r.get(3)?,
+                    })
+                },
+            )?;
+            let channels = db.query_all(
+                "SELECT peer_id, pts FROM session_channel_state \
+                 WHERE rowid IN (SELECT max(rowid) FROM session_channel_state GROUP BY peer_id)",
+                [],
+                |r| {
+                    Ok(ChannelStateRow {
+                        peer_id: r.get(0)?,
+                        pts: r.get(1)?,
+                    })
+                },
+            )?;
+            Ok(SessionSnapshot {
+                home_dc,
+                dc_options,
+                update_state,
+                channels,
+            })
+        })
+        .await
+    }
+
+    async fn peer(&self, peer_id: Option<i64>) -> Result<Option<PeerRow>> {
+        self.run(move |db| {
+            let map = |r: &rusqlite::Row<'_>| -> rusqlite::Result<PeerRow> {
+                Ok(PeerRow {
+                    peer_id: r.get(0)?,
+                    hash: r.get(1)?,
+                    subtype: r.get(2)?,
+                })
+            };
+            match peer_id {
+                Some(id) => db.query_opt(
+                    "SELECT peer_id, hash, subtype FROM peer_cache WHERE peer_id = ?1 ORDER BY rowid DESC LIMIT 1",
+                    params![id],
+                    map,
+                ),
+                None => db.query_opt(
+                    "SELECT peer_id, hash, subtype FROM peer_cache \
+                     WHERE subtype IS NOT NULL AND (subtype & 1) = 1 ORDER BY rowid DESC LIMIT 1",
+                    [],
+                    map,
+                ),
+            }
+        })
+        .await
+    }
+
+    async fn clear_channel_states(&self) -> Result<()> {
+        self.run(move |db| {
+            let conn = db.conn.lock().unwrap();
+            match conn.execute("DELETE FROM session_channel_state", []) {
+                Err(e) if !missing_schema(&e) => Err(e.into()),
+                _ => Ok(()),
+            }
+        })
+        .await
+    }
+}
@@ -2,7 +2,6 @@ use std::collections::HashMap;
 use std::sync::Mutex;
 use std::time::Duration;
 
-use clickhouse::Row;
 use futures_core::future::BoxFuture;
 use grammers_session::types::{
     ChannelKind, ChannelState, DcOption, PeerAuth, PeerId, PeerInfo, PeerKind, UpdateState,
@@ -10,45 +9,11 @@ use grammers_session::types::{
 };
 use grammers_session::{Session, SessionData};
 use log::{debug, error, warn};
-use serde::{Deserialize, Serialize};
 
-use crate::db::clickhouse;
-
-// ── ClickHouse row types ────────────────────────────────────────────
-
-#[derive(Row, Serialize, Deserialize)]
-struct PeerRow {
-    peer_id: i64,
-    hash: Option<i64>,
-    subtype: Option<u8>,
-}
-
-#[derive(Row, Serialize, Deserialize)]
-struct DcHomeRow {
-    dc_id: i32,
-}
-
-#[derive(Row, Serialize, Deserialize)]
-struct DcOptionRow {
-    dc_id: i32,
-    ipv4: String,
-    ipv6: String,
-    auth_key: Option<String>,
-}
-
-#[derive(Row, Serialize, Deserialize)]
-struct UpdateStateRow {
-    pts: i32,
-    qts: i32,
-    date: i32,
-    seq: i32,
-}
-
-#[derive(Row, Serialize, Deserialize)]
-struct ChannelStateRow {
-    peer_id: i64,
-    pts: i32,
-}
+use crate::storage::{
+    ChannelStateRow, DcHomeRow, DcOptionRow, PeerRow, Storage, StorageError, UpdateStateRow,
+    storage,
+};
 
 // ── In-memory cache ─────────────────────────────────────────────────
 
@@ -58,43 +23,36 @@ struct Cache {
     updates: UpdatesState,
 }
 
-// ── ClickhouseSession ───────────────────────────────────────────────
+// ── StorageSession ──────────────────────────────────────────────────
 
-pub struct ClickhouseSession {
+pub struct StorageSession {
     cache: Mutex<Cache>,
+    /// Whether update state is written to storage or only kept in memory.
+    persist_updates: bool,
 }
 
-impl ClickhouseSession {
+impl StorageSession {
     pub async fn open() -> Result<Self, Box<dyn std::error::Error>> {
         let defaults = SessionData::default();
 
-        let home_dc = clickhouse()
-            .query("SELECT dc_id FROM session_dc_home FINAL WHERE key = 1 LIMIT 1")
-            .fetch_one::<DcHomeRow>()
-            .await
-            .ok()
-            .map(|r| r.dc_id)
-            .unwrap_or(defaults.home_dc);
+        let snapshot = storage().load_session().await.unwrap_or_else(|e| {
+            warn!("failed to load session from storage: {e}");
+            Default::default()
+        });
+
+        let home_dc = snapshot.home_dc.unwrap_or(defaults.home_dc);
 
         // Load dc_options
         let mut dc_options: HashMap<i32, DcOption> = defaults.dc_options;
-        let rows: Vec<DcOptionRow> = clickhouse()
-            .query("SELECT dc_id, ipv4, ipv6, auth_key FROM session_dc_option FINAL")
-            .fetch_all()
-            .await
-            .unwrap_or_default();
-        for row in rows {
-            if let Some(opt) = dc_option_from_row(&row) {
+        for row in &snapshot.dc_options {
+            if let Some(opt) = dc_option_from_row(row) {
                 dc_options.insert(opt.id, opt);
             }
         }
 
         // Load updates state
-        let updates = clickhouse()
-            .query("SELECT pts, qts, date, seq FROM session_update_state FINAL WHERE key = 1 LIMIT 1")
-            .fetch_one::<UpdateStateRow>()
-            .await
-            .ok()
+        let updates = snapshot
+            .update_state
             .map(|r| UpdatesState {
                 pts: r.pts,
                 qts: r.qts,
@@ -104,14 +62,9 @@ impl ClickhouseSession {
             })
             .unwrap_or_default();
 
-        let channels: Vec<ChannelStateRow> = clickhouse()
-            .query("SELECT peer_id, pts FROM session_channel_state FINAL")
-            .fetch_all()
-            .await
-            .unwrap_or_default();
-
         let updates = UpdatesState {
-            channels: channels
+            channels: snapshot
+                .channels
                 .into_iter()
                 .map(|r| ChannelState {
                     id: r.peer_id,
@@ -127,8 +80,21 @@ impl ClickhouseSession {
                 dc_options,
                 updates,
             }),
+            persist_updates: true,
         })
     }
+
+    /// Keep update state in memory only, for connections that run next to the
+    /// bot and must not move its catch-up position.
+    pub fn without_update_persistence(mut self) -> Self {
+        self.persist_updates = false;
+        self
+    }
+
+    /// Date of the stored update state, 0 if there is none.
+    pub fn update_state_date(&self) -> i32 {
+        self.cache.lock().unwrap().updates.date
+    }
 }
 
 // ── Peer encoding / decoding ────────────────────────────────────────
@@ -193,7 +159,7 @@ fn decode_peer(peer_id: PeerId, row: &PeerRow) -> PeerInfo {
     }
 }
 
-// ── DcOption ↔ ClickHouse helpers ───────────────────────────────────
+// ── DcOption ↔ storage helpers ───────────────────────────────────
 
 fn auth_key_to_hex(key: &[u8; 256]) -> String {
     key.iter().map(|b| format!("{b:02x}")).collect()
@@ -231,13 +197,25 @@ fn dc_option_from_row(row: &DcOptionRow) -> Option<DcOption> {
 
 // ── Session trait ───────────────────────────────────────────────────
 
-impl Session for ClickhouseSession {
-    // Writes are best-effort (in-memory cache is the source of truth, ClickHouse is
+/// Write one row, logging instead of failing: the in-memory cache stays the
+/// source of truth even when storage is unreachable.
+async fn persist<T>(table: &str, row: T)
+where
+    T: serde::Serialize + Send + Sync,
+    for<'a> T: clickhouse::Row<Value<'a> = T>,
+{
+    if let Err(e) = storage().insert(table, &[row]).await {
+        error!("failed to write {table}: {e}");
+    }
+}
+
+impl Session for StorageSession {
+    // Writes are best-effort (in-memory cache is the source of truth, storage is
     // write-behind persistence), so the write methods always return `Ok` and only log
-    // failures. The one method that genuinely reads from ClickHouse — `peer` — retries
-    // transient failures and surfaces a real error if ClickHouse stays unreachable,
+    // failures. The one method that genuinely reads from storage — `peer` — retries
+    // transient failures and surfaces a real error if storage stays unreachable,
     // instead of masking an outage as a missing peer.
-    type Error = clickhouse::error::Error;
+    type Error = StorageError;
 
     fn home_dc_id(&self) -> Result<i32, Self::Error> {
         Ok(self.cache.lock().unwrap().home_dc)
@@ -246,13 +224,7 @@ impl Session for ClickhouseSession {
     fn set_home_dc_id(&self, dc_id: i32) -> BoxFuture<'_, Result<(), Self::Error>> {
         self.cache.lock().unwrap().home_dc = dc_id;
         Box::pin(async move {
-            if let Ok(mut ins) = clickhouse().insert::<DcHomeRow>("session_dc_home").await {
-                if let Err(e) = ins.write(&DcHomeRow { dc_id }).await {
-                    error!("failed to write home_dc to clickhouse: {e}");
-                } else if let Err(e) = ins.end().await {
-                    error!("failed to flush home_dc to clickhouse: {e}");
-                }
-            }
+            persist("session_dc_home", DcHomeRow { dc_id }).await;
             Ok(())
         })
     }
@@ -270,13 +242,7 @@ impl Session for ClickhouseSession {
 
         let row = dc_option_to_row(dc_option);
         Box::pin(async move {
-            if let Ok(mut ins) = clickhouse().insert::<DcOptionRow>("session_dc_option").await {
-                if let Err(e) = ins.write(&row).await {
-                    error!("failed to write dc_option to clickhouse: {e}");
-                } else if let Err(e) = ins.end().await {
-                    error!("failed to flush dc_option to clickhouse: {e}");
-                }
-            }
+            persist("session_dc_option", row).await;
             Ok(())
         })
     }
@@ -284,49 +250,30 @@ impl Session for ClickhouseSession {
     fn peer(&self, peer: PeerId) -> BoxFuture<'_, Result<Option<PeerInfo>, Self::Error>> {
         Box::pin(async move {
             const MAX_ATTEMPTS: u32 = 5;
-            let is_self_query = peer.bot_api_dialog_id().is_none();
+            let dialog_id = peer.bot_api_dialog_id();
 
             let mut attempt = 0;
             loop {
                 attempt += 1;
 
-                let result = if !is_self_query {
-                    let dialog_id = peer.bot_api_dialog_id().unwrap();
-                    clickhouse()
-                        .query(
-                            "SELECT peer_id, hash, subtype FROM peer_cache FINAL WHERE peer_id = ?",
-                        )
-                        .bind(dialog_id)
-                        .fetch_one::<PeerRow>()
-                        .await
-                } else {
-                    clickhouse()
-                        .query(
-                            "SELECT peer_id, hash, subtype FROM peer_cache FINAL \
-                             WHERE subtype IS NOT NULL AND bitAnd(subtype, 1) = 1 LIMIT 1",
-                        )
-                        .fetch_one::<PeerRow>()
-                        .await
-                };
-
-                match result {
-                    Ok(row) => {
-                        let resolved = if is_self_query {
-                            debug!("self user found in clickhouse (peer_id={})", row.peer_id);
+                match storage().peer(dialog_id).await {
+                    Ok(Some(row)) => {
+                        let resolved = if dialog_id.is_none() {
+                            debug!("self user found in storage (peer_id={})", row.peer_id);
                             PeerId::user_unchecked(row.peer_id)
                         } else {
-                            debug!("peer {:?} found in clickhouse", peer);
+                            debug!("peer {:?} found in storage", peer);
                             peer
                         };
                         return Ok(Some(decode_peer(resolved, &row)));
                     }
                     // Genuine cache miss: the peer simply isn't stored. Return `None`
                     // so grammers resolves it from the network.
-                    Err(clickhouse::error::Error::RowNotFound) => {
-                        debug!("peer {:?} not in clickhouse", peer);
+                    Ok(None) => {
+                        debug!("peer {:?} not in storage", peer);
                         return Ok(None);
                     }
-                    // Transient failure (ClickHouse down, network blip): retry a few
+                    // Transient failure (storage down, network blip): retry a few
                     // times so a brief outage isn't mistaken for a missing peer.
                     Err(e) if attempt < MAX_ATTEMPTS => {
                         warn!(
@@ -357,19 +304,7 @@ impl Session for ClickhouseSession {
                 hash: peer.auth().map(|a| a.hash()),
                 subtype: encode_subtype(&peer),
             };
-
-            match clickhouse().insert::<PeerRow>("peer_cache").await {
-                Ok(mut insert) => {
-                    if let Err(e) = insert.write(&row).await {
-                        error!("failed to write peer {} to clickhouse: {}", row.peer_id, e);
-                    } else if let Err(e) = insert.end().await {
-                        error!("failed to flush peer {} to clickhouse: {}", row.peer_id, e);
-                    }
-                }
-                Err(e) => {
-                    error!("failed to insert peer {} to clickhouse: {}", row.peer_id, e);
-                }
-            }
+            persist("peer_cache", row).await;
             Ok(())
         })
     }
@@ -408,46 +343,39 @@ impl Session for ClickhouseSession {
                 }
             }
 
-            // Persist to ClickHouse
+            if !self.persist_updates {
+                return Ok(());
+            }
+
+            // Persist to storage
             match &update {
                 UpdateState::All(state) => {
                     // Write full update_state
-                    if let Ok(mut ins) = clickhouse()
-                        .insert::<UpdateStateRow>("session_update_state")
-                        .await
-                    {
-                        let _ = ins
-                            .write(&UpdateStateRow {
-                                pts: state.pts,
-                                qts: state.qts,
-                                date: state.date,
-                                seq: state.seq,
-                            })
-                            .await;
-                        let _ = ins.end().await;
-                    }
-
-                    // Replace all channel states: truncate + re-insert
-                    if let Err(e) = clickhouse()
-                        .query("TRUNCATE TABLE session_channel_state")
-                        .execute()
-                        .await
-                    {
-                        warn!("failed to truncate channel_state: {e}");
+                    persist(
+                        "session_update_state",
+                        UpdateStateRow {
+                            pts: state.pts,
+                            qts: state.qts,
+                            date: state.date,
+                            seq: state.seq,
+                        },
+                    )
+                    .await;
+
+                    // Replace all channel states: clear + re-insert
+                    if let Err(e) = storage().clear_channel_states().await {
+                        warn!("failed to clear channel_state: {e}");
                     }
-                    for ch in &state.channels {
-                        if let Ok(mut ins) = clickhouse()
-                            .insert::<ChannelStateRow>("session_channel_state")
-                            .await
-                        {
-                            let _ = ins
-                                .write(&ChannelStateRow {
-                                    peer_id: ch.id,
-                                    pts: ch.pts,
-                                })
-                                .await;
-                            let _ = ins.end().await;
-                        }
+                    let rows: Vec<ChannelStateRow> = state
+                        .channels
+                        .iter()
+                        .map(|ch| ChannelStateRow {
+                            peer_id: ch.id,
+                            pts: ch.pts,
+                        })
+                        .collect();
+                    if let Err(e) = storage().insert("session_channel_state", &rows).await {
+                        error!("failed to write session_channel_state: {e}");
                     }
                 }
                 UpdateState::Primary { pts, date, seq } => {
@@ -460,13 +388,7 @@ impl Session for ClickhouseSession {
                             seq: *seq,
                         }
                     };
-                    if let Ok(mut ins) = clickhouse()
-                        .insert::<UpdateStateRow>("session_update_state")
-                        .await
-                    {
-                        let _ = ins.write(&row).await;
-                        let _ = ins.end().await;
-                    }
+                    persist("session_update_state", row).await;
                 }
                 UpdateState::Secondary { qts } => {
                     let row = {
@@ -478,27 +400,17 @@ impl Session for ClickhouseSession {
                             seq: cache.updates.seq,
                         }
                     };
-                    if let Ok(mut ins) = clickhouse()
-                        .insert::<UpdateStateRow>("session_update_state")
-                        .await
-                    {
-                        let _ = ins.write(&row).await;
-                        let _ = ins.end().await;
-                    }
+                    persist("session_update_state", row).await;
                 }
                 UpdateState::Channel { id, pts } => {
-                    if let Ok(mut ins) = clickhouse()
-                        .insert::<ChannelStateRow>("session_channel_state")
-                        .await
-                    {
-                        let _ = ins
-                            .write(&ChannelStateRow {
-                                peer_id: *id,
-                                pts: *pts,
-                            })
-                            .await;
-                        let _ = ins.end().await;
-                    }
+                    persist(
+                        "session_channel_state",
+                        ChannelStateRow {
+                            peer_id: *id,
+                            pts: *pts,
+                        },
+                    )
+                    .await;
                 }
             }
             Ok(())
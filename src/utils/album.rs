@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use log::info;
+
+/// Album items arrive as separate messages in quick succession. They are
+/// collected per (chat, grouped_id) and printed as one console line once no
+/// new item has arrived for `QUIET`.
+static ALBUMS: LazyLock<Mutex<HashMap<(i64, i64), Album>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+const QUIET: Duration = Duration::from_secs(1);
+
+pub struct AlbumItem {
+    /// "incoming" or "outgoing".
+    pub label: &'static str,
+    /// ANSI color of the console line.
+    pub color: u8,
+    pub chat_id: i64,
+    pub grouped_id: i64,
+    pub message_id: i32,
+    pub chat: String,
+    pub sender: String,
+    /// Media description like "[photo]".
+    pub media: String,
+    /// Text of the item; usually only one item carries the caption.
+    pub caption: String,
+    /// Prefix such as "[fwd from …]".
+    pub prefix: String,
+}
+
+struct Album {
+    items: Vec<AlbumItem>,
+    last: Instant,
+}
+
+/// Collect an album item; the album is printed after it is complete.
+pub fn add(item: AlbumItem) {
+    let key = (item.chat_id, item.grouped_id);
+    let mut albums = ALBUMS.lock().unwrap();
+    if let Some(album) = albums.get_mut(&key) {
+        album.items.push(item);
+        album.last = Instant::now();
+        return;
+    }
+    albums.insert(
+        key,
+        Album {
+            items: vec![item],
+            last: Instant::now(),
+        },
+    );
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(QUIET).await;
+            let mut albums = ALBUMS.lock().unwrap();
+            if albums.get(&key).is_some_and(|a| a.last.elapsed() < QUIET) {
+                continue;
+            }
+            if let Some(album) = albums.remove(&key) {
+                drop(albums);
+                print(album);
+            }
+            return;
+        }
+    });
+}
+
+fn print(mut album: Album) {
+    album.items.sort_by_key(|i| i.message_id);
+    let first = &album.items[0];
+    let kinds: Vec<&str> = album
+        .items
+        .iter()
+        .map(|i| {
+            i.media
+                .trim_start_matches('[')
+                .split([',', ']'])
+                .next()
+                .unwrap_or_default()
+        })
+        .collect();
+    let caption = album
+        .items
+        .iter()
+        .map(|i| i.caption.as_str())
+        .find(|c| !c.is_empty())
+        .unwrap_or_default();
+    let mut preview = format!("[album of {}: {}]", kinds.len(), kinds.join(", "));
+    if !first.prefix.is_empty() {
+        preview = format!("{} {preview}", first.prefix);
+    }
+    if !caption.is_empty() {
+        preview = format!("{preview} {caption}");
+    }
+    let color = first.color;
+    info!(
+        "\x1b[{color}m{:<8} {:>8} {:<25} \x1b[90m│\x1b[{color}m {:<10} \x1b[90m│\x1b[{color}m {}\x1b[0m",
+        first.label, first.message_id, first.chat, first.sender, preview
+    );
+}
@@ -1,5 +1,12 @@
 use similar::{ChangeTag, TextDiff};
 
+pub fn unified_diff(original: &str, modified: &str) -> String {
+    TextDiff::from_lines(original, modified)
+        .unified_diff()
+        .missing_newline_hint(false)
+        .to_string()
+}
+
 pub fn colorize_unified_diff(diff: &str, original: &str, modified: &str) -> String {
     let lines: Vec<&str> = diff.lines().collect();
     let mut result = String::new();
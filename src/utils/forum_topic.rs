@@ -0,0 +1,171 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use grammers_client::Client;
+use grammers_client::message::Message;
+use grammers_tl_types as tl;
+use log::debug;
+
+/// Topic titles per (chat, topic id), learned from topic service messages or
+/// fetched once per topic. Holds at most `MAX_TOPICS` and evicts the oldest first.
+static TITLES: LazyLock<Mutex<Titles>> = LazyLock::new(|| {
+    Mutex::new(Titles {
+        entries: HashMap::new(),
+        order: VecDeque::new(),
+    })
+});
+
+const MAX_TOPICS: usize = 10_000;
+/// How long a failed or empty fetch is remembered before the topic is tried again.
+const RETRY_AFTER: Duration = Duration::from_secs(600);
+/// How long the first message of an unknown topic waits for the fetch.
+const FIRST_WAIT: Duration = Duration::from_secs(2);
+
+enum Title {
+    Known(String),
+    Fetching,
+    /// The fetch failed or found no such topic.
+    Missing(Instant),
+}
+
+struct Titles {
+    entries: HashMap<(i64, i32), Title>,
+    /// First-insertion order, oldest first.
+    order: VecDeque<(i64, i32)>,
+}
+
+impl Titles {
+    fn insert(&mut self, key: (i64, i32), title: Title) {
+        if self.entries.insert(key, title).is_none() {
+            self.order.push_back(key);
+        }
+        while self.entries.len() > MAX_TOPICS {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+/// Forum topic a message belongs to, 0 outside forums and in the General topic.
+pub fn topic_id(message: &Message) -> i32 {
+    if let Some(tl::enums::MessageAction::TopicCreate(_)) = message.action() {
+        // The topic is identified by the message that created it.
+        return message.id();
+    }
+    match message.reply_header() {
+        Some(tl::enums::MessageReplyHeader::Header(h)) if h.forum_topic => h
+            .reply_to_top_id
+            .or(h.reply_to_msg_id)
+            .unwrap_or_default(),
+        _ => 0,
+    }
+}
+
+/// Title of a forum topic, empty if it is not known yet.
+///
+/// An unknown title is fetched, and the message that triggered the fetch waits
+/// up to `FIRST_WAIT` for it; a slower fetch finishes in the background and
+/// only later messages get the name. Failed fetches are retried after
+/// `RETRY_AFTER`.
+pub async fn topic_name(client: &Client, message: &Message, topic_id: i32) -> String {
+    if topic_id == 0 {
+        return String::new();
+    }
+    let chat_id = message.peer_id().bare_id_unchecked();
+    let key = (chat_id, topic_id);
+    let learned = match message.action() {
+        Some(tl::enums::MessageAction::TopicCreate(a)) => Some(a.title.clone()),
+        Some(tl::enums::MessageAction::TopicEdit(a)) => a.title.clone(),
+        _ => None,
+    };
+    {
+        let mut titles = TITLES.lock().unwrap();
+        if let Some(title) = learned {
+            titles.insert(key, Title::Known(title.clone()));
+            return title;
+        }
+        match titles.entries.get(&key) {
+            Some(Title::Known(title)) => return title.clone(),
+            Some(Title::Fetching) => return String::new(),
+            Some(Title::Missing(at)) if at.elapsed() < RETRY_AFTER => return String::new(),
+            _ => {}
+        }
+        titles.insert(key, Title::Fetching);
+    }
+
+    let client = client.clone();
+    let message = Message::clone(message);
+    let fetched = tokio::spawn(async move {
+        let title = match fetch(&client, &message, topic_id).await {
+            Ok(Some(title)) => title,
+            Ok(None) => {
+                debug!("topic {topic_id} of chat {chat_id} not found");
+                String::new()
+            }
+            Err(e) => {
+                debug!("cannot fetch topic {topic_id} of chat {chat_id}: {e}");
+                String::new()
+            }
+        };
+        let cached = if title.is_empty() {
+            Title::Missing(Instant::now())
+        } else {
+            Title::Known(title.clone())
+        };
+        TITLES.lock().unwrap().insert(key, cached);
+        title
+    });
+    tokio::time::timeout(FIRST_WAIT, fetched)
+        .await
+        .ok()
+        .and_then(Result::ok)
+        .unwrap_or_default()
+}
+
+async fn fetch(
+    client: &Client,
+    message: &Message,
+    topic_id: i32,
+) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let peer = message
+        .peer()
+        .ok_or("chat unknown")?
+        .to_ref()
+        .await?
+        .ok_or("no access hash")?;
+    let tl::enums::messages::ForumTopics::Topics(result) = client
+        .invoke(&tl::functions::messages::GetForumTopicsById {
+            peer: peer.into(),
+            topics: vec![topic_id],
+        })
+        .await?;
+    Ok(result.topics.iter().find_map(|t| match t {
+        tl::enums::ForumTopic::Topic(t) if t.id == topic_id => Some(t.title.clone()),
+        _ => None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_TOPICS, Title, Titles};
+
+    #[test]
+    fn titles_evict_the_oldest_topic() {
+        let mut titles = Titles {
+            entries: Default::default(),
+            order: Default::default(),
+        };
+        for id in 0..=MAX_TOPICS as i32 {
+            titles.insert((1, id), Title::Known(id.to_string()));
+        }
+        // Replacing an entry keeps its place.
+        titles.insert((1, 5), Title::Fetching);
+        assert_eq!(titles.entries.len(), MAX_TOPICS);
+        assert!(!titles.entries.contains_key(&(1, 0)));
+        assert!(matches!(titles.entries.get(&(1, 5)), Some(Title::Fetching)));
+        assert!(titles.entries.contains_key(&(1, MAX_TOPICS as i32)));
+    }
+}
@@ -2,7 +2,18 @@ use grammers_client::update::Message;
 use grammers_tl_types as tl;
 
 pub fn format_buttons(message: &Message) -> Option<String> {
-    let markup = extract_reply_markup(&message.raw)?;
+    format_markup(extract_reply_markup(&message.raw)?)
+}
+
+/// Same as `format_buttons`, for a raw message (e.g. one fetched rather than received).
+pub fn format_buttons_of_message(msg: &tl::enums::Message) -> Option<String> {
+    match msg {
+        tl::enums::Message::Message(m) => format_markup(m.reply_markup.as_ref()?),
+        _ => None,
+    }
+}
+
+fn format_markup(markup: &tl::enums::ReplyMarkup) -> Option<String> {
     let tl::enums::ReplyMarkup::ReplyInlineMarkup(inline) = markup else {
         return None;
     };
@@ -1,20 +1,44 @@
-use std::sync::LazyLock;
+use std::collections::HashSet;
+use std::sync::{LazyLock, RwLock};
 
-static IGNORED_CHAT_IDS: LazyLock<Vec<i64>> = LazyLock::new(|| {
-    std::env::var("LOG_IGNORE_CHATS")
-        .unwrap_or_default()
-        .split(',')
-        .filter_map(|s| s.trim().parse::<i64>().ok())
-        .collect()
+/// Bare chat ids, seeded from `LOG_IGNORE_CHATS` (Bot API form); the `.ignore`
+/// command changes it at runtime.
+static IGNORED_CHAT_IDS: LazyLock<RwLock<HashSet<i64>>> = LazyLock::new(|| {
+    RwLock::new(
+        std::env::var("LOG_IGNORE_CHATS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|s| s.trim().parse::<i64>().ok())
+            .map(crate::utils::peer::bare_id)
+            .collect(),
+    )
 });
 
 pub fn is_log_ignored(chat_id: i64) -> bool {
-    IGNORED_CHAT_IDS.contains(&chat_id)
+    IGNORED_CHAT_IDS.read().unwrap().contains(&chat_id)
+}
+
+/// Start or stop ignoring a chat; returns whether it is ignored now.
+pub fn toggle(chat_id: i64) -> bool {
+    let mut ids = IGNORED_CHAT_IDS.write().unwrap();
+    if ids.remove(&chat_id) {
+        false
+    } else {
+        ids.insert(chat_id);
+        true
+    }
+}
+
+pub fn ignored() -> Vec<i64> {
+    let mut ids: Vec<i64> = IGNORED_CHAT_IDS.read().unwrap().iter().copied().collect();
+    ids.sort_unstable();
+    ids
 }
 
 /// Check if a log message mentions an ignored chat via `Channel(ID)` or `Chat(ID)`.
 pub fn is_message_ignored(msg: &str) -> bool {
-    if IGNORED_CHAT_IDS.is_empty() {
+    let ids = IGNORED_CHAT_IDS.read().unwrap();
+    if ids.is_empty() {
         return false;
     }
     for keyword in ["Channel(", "Chat("] {
@@ -22,7 +46,7 @@ pub fn is_message_ignored(msg: &str) -> bool {
             let after = &msg[start + keyword.len()..];
             if let Some(end) = after.find(')') {
                 if let Ok(id) = after[..end].parse::<i64>() {
-                    if IGNORED_CHAT_IDS.contains(&id) {
+                    if ids.contains(&id) {
                         return true;
                     }
                 }
@@ -6,7 +6,7 @@ pub fn describe(message: &Message) -> Option<String> {
     Some(describe_media(media))
 }
 
-fn extract_media(message: &Message) -> Option<&tl::enums::MessageMedia> {
+pub fn extract_media(message: &Message) -> Option<&tl::enums::MessageMedia> {
     // message.raw is tl::enums::Update; we need to get the inner tl::types::Message
     // and its media field. The grammers Message type exposes raw as pub.
     // We go through the grammers high-level API instead: check if media() returns Some,
@@ -27,6 +27,36 @@ fn extract_media(message: &Message) -> Option<&tl::enums::MessageMedia> {
     extract_media_from_update(&message.raw)
 }
 
+/// Same as `describe`, for a raw message (e.g. one fetched rather than received).
+pub fn describe_message(msg: &tl::enums::Message) -> Option<String> {
+    extract_media_from_message(msg).map(describe_media)
+}
+
+/// Id of the photo or document, so a replaced file is noticed even when its
+/// description stays the same; 0 for other media and messages without media.
+pub fn media_id(message: &Message) -> i64 {
+    extract_media(message).map(media_id_of).unwrap_or_default()
+}
+
+/// Same as `media_id`, for a raw message.
+pub fn media_id_of_message(msg: &tl::enums::Message) -> i64 {
+    extract_media_from_message(msg).map(media_id_of).unwrap_or_default()
+}
+
+fn media_id_of(media: &tl::enums::MessageMedia) -> i64 {
+    match media {
+        tl::enums::MessageMedia::Photo(p) => match &p.photo {
+            Some(tl::enums::Photo::Photo(photo)) => photo.id,
+            _ => 0,
+        },
+        tl::enums::MessageMedia::Document(d) => match &d.document {
+            Some(tl::enums::Document::Document(doc)) => doc.id,
+            _ => 0,
+        },
+        _ => 0,
+    }
+}
+
 fn extract_media_from_update(update: &tl::enums::Update) -> Option<&tl::enums::MessageMedia> {
     let msg = match update {
         tl::enums::Update::NewMessage(u) => &u.message,
@@ -36,6 +66,10 @@ fn extract_media_from_update(update: &tl::enums::Update) -> Option<&tl::enums::M
         tl::enums::Update::NewScheduledMessage(u) => &u.message,
         _ => return None,
     };
+    extract_media_from_message(msg)
+}
+
+fn extract_media_from_message(msg: &tl::enums::Message) -> Option<&tl::enums::MessageMedia> {
     match msg {
         tl::enums::Message::Message(m) => m.media.as_ref(),
         tl::enums::Message::Empty(_) | tl::enums::Message::Service(_) => None,
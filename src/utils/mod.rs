@@ -1,7 +1,11 @@
+pub mod album;
 pub mod diff;
 pub mod format_entities;
+pub mod forum_topic;
 pub mod inline_buttons;
 pub mod log_ignore;
 pub mod media_description;
+pub mod peer;
 pub mod reply_preview;
 pub mod service_action;
+pub mod text;
@@ -0,0 +1,83 @@
+use grammers_client::Client;
+use grammers_client::peer::Peer;
+use grammers_session::types::PeerRef;
+use grammers_tl_types as tl;
+
+use crate::storage::{CHANNEL_ID_OFFSET, Storage, storage};
+
+/// Resolve a chat id in Bot API form: users positive, basic groups negative,
+/// channels and supergroups `-100…`. The access hash comes from the session's
+/// peer cache, so users and channels must have been seen before.
+pub async fn resolve(
+    client: &Client,
+    id: i64,
+) -> Result<PeerRef, Box<dyn std::error::Error + Send + Sync>> {
+    resolve_peer(client, id)
+        .await?
+        .to_ref()
+        .await?
+        .ok_or_else(|| format!("no access hash for chat {id}").into())
+}
+
+/// Like `resolve`, keeping the peer's names.
+pub async fn resolve_peer(
+    client: &Client,
+    id: i64,
+) -> Result<Peer, Box<dyn std::error::Error + Send + Sync>> {
+    // Basic groups need no access hash.
+    if id < 0 && id > CHANNEL_ID_OFFSET {
+        let input_peer = tl::types::InputPeerChat { chat_id: -id }.into();
+        return Ok(client.resolve_peer(input_peer).await?);
+    }
+    let access_hash = storage()
+        .peer(Some(id))
+        .await?
+        .and_then(|p| p.hash)
+        .ok_or_else(|| format!("no access hash for chat {id}, it was never seen"))?;
+    let input_peer: tl::enums::InputPeer = if id > 0 {
+        tl::types::InputPeerUser {
+            user_id: id,
+            access_hash,
+        }
+        .into()
+    } else {
+        tl::types::InputPeerChannel {
+            channel_id: CHANNEL_ID_OFFSET - id,
+            access_hash,
+        }
+        .into()
+    };
+    Ok(client.resolve_peer(input_peer).await?)
+}
+
+/// Bare id of a chat id given in Bot API form, the form chat ids are stored in.
+/// Every chat id a user types or configures goes through this; bare ids are
+/// positive and pass through unchanged, so they keep working too.
+pub fn bare_id(id: i64) -> i64 {
+    if id <= CHANNEL_ID_OFFSET {
+        CHANNEL_ID_OFFSET - id
+    } else {
+        id.abs()
+    }
+}
+
+/// Bot API form of a TL peer, the inverse of `resolve`.
+pub fn bot_api_id(peer: &tl::enums::Peer) -> i64 {
+    match peer {
+        tl::enums::Peer::User(p) => p.user_id,
+        tl::enums::Peer::Chat(p) => -p.chat_id,
+        tl::enums::Peer::Channel(p) => CHANNEL_ID_OFFSET - p.channel_id,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bare_id;
+
+    #[test]
+    fn bare_id_accepts_bot_api_and_bare_ids() {
+        assert_eq!(bare_id(1_234_567_890), 1_234_567_890);
+        assert_eq!(bare_id(-4_567), 4_567);
+        assert_eq!(bare_id(-1_001_234_567_890), 1_234_567_890);
+    }
+}
@@ -1,6 +1,8 @@
 use grammers_client::update::Message;
 use grammers_tl_types as tl;
 
+use crate::storage::Storage;
+
 /// Format reply line for log messages.
 /// Returns a line to print *above* the message, or empty string if no reply.
 pub async fn format_reply_line(message: &Message) -> String {
@@ -63,7 +65,11 @@ fn highlight_quote(text: &str, quote: &str) -> String {
 }
 
 async fn lookup_message_text(chat_id: i64, message_id: i32) -> (Option<String>, Option<String>) {
-    // Check unflushed incoming buffer first
+    if let Some(m) = crate::message_cache::get(chat_id, message_id as i64) {
+        let sender = m.sender();
+        return (Some(m.text), sender);
+    }
+    // Then unflushed incoming buffer
     let from_buf = crate::db::INCOMING_BUF.find_last(|m| {
         if m.chat_id == chat_id && m.message_id == message_id as i64 {
             let sender = if m.second_name.is_empty() {
@@ -79,29 +85,27 @@ async fn lookup_message_text(chat_id: i64, message_id: i32) -> (Option<String>,
     if let Some((text, sender)) = from_buf {
         return (Some(text), Some(sender));
     }
+    let from_out_buf = crate::db::OUTGOING_BUF.find_last(|m| {
+        (m.id == chat_id && m.message_id == message_id as u64).then(|| m.message.clone())
+    }).await;
+    if let Some(text) = from_out_buf {
+        return (Some(text), None);
+    }
 
-    // Query ClickHouse: try chats_log (incoming), then telegram_messages_new (outgoing)
-    let db = crate::db::clickhouse();
+    // Query storage: try chats_log (incoming), then telegram_messages_new (outgoing)
+    let storage = crate::storage::storage();
 
-    if let Ok((text, first, second)) = db
-        .query("SELECT message, first_name, second_name FROM chats_log WHERE chat_id = ? AND message_id = ? ORDER BY date_time DESC LIMIT 1")
-        .bind(chat_id)
-        .bind(message_id as i64)
-        .fetch_one::<(String, String, String)>()
-        .await
-    {
-        let sender = if second.is_empty() { first } else { format!("{first} {second}") };
-        return (Some(text), Some(sender));
+    if let Ok(Some(m)) = storage.incoming(chat_id, message_id as i64).await {
+        let sender = if m.second_name.is_empty() {
+            m.first_name
+        } else {
+            format!("{} {}", m.first_name, m.second_name)
+        };
+        return (Some(m.message), Some(sender));
     }
 
-    if let Ok(text) = db
-        .query("SELECT message FROM telegram_messages_new WHERE id = ? AND message_id = ? ORDER BY date_time DESC LIMIT 1")
-        .bind(chat_id)
-        .bind(message_id as u64)
-        .fetch_one::<String>()
-        .await
-    {
-        return (Some(text), None);
+    if let Ok(Some(m)) = storage.outgoing(chat_id, message_id as i64).await {
+        return (Some(m.message), None);
     }
 
     (None, None)
@@ -0,0 +1,12 @@
+/// Telegram rejects longer messages.
+pub const MAX_MESSAGE_CHARS: usize = 4096;
+
+/// Cut text to fit in one Telegram message.
+pub fn truncate_message(text: String) -> String {
+    if text.chars().count() <= MAX_MESSAGE_CHARS {
+        return text;
+    }
+    let mut cut: String = text.chars().take(MAX_MESSAGE_CHARS - 1).collect();
+    cut.push('…');
+    cut
+}